@@ -0,0 +1,168 @@
+//! Compares the two `--concurrency-model`s of `cargo depsize`:
+//!
+//! * `async` (`tasks`): the walk runs inside ordinary tokio tasks and every
+//!   file is stat'ed with `tokio::fs::metadata`, one blocking-pool round trip
+//!   each.
+//! * `blocking`: each package is walked on tokio's blocking pool, taking the
+//!   size from the walker's own entries.
+//!
+//! and, within `blocking`, the sequential walk with the parallel one that
+//! packages with more than `PARALLEL_WALK_ENTRIES` entries switch to, over
+//! every package and over the one with the most entries alone.
+//!
+//! Every direct subdirectory of the given directory counts as one package.
+//! It defaults to the unpacked registry sources under `$CARGO_HOME`, which
+//! is what `cargo depsize` spends most of its time on:
+//!
+//!     cargo bench --bench walk -- [DIR]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+const RUNS: usize = 5;
+
+fn main() {
+    let dir = env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .map(PathBuf::from)
+        .unwrap_or_else(default_dir);
+    let packages = packages(&dir);
+    println!("{} packages under {}", packages.len(), dir.display());
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let async_bytes = runtime.block_on(walk_async(packages.clone()));
+    let blocking_bytes = runtime.block_on(walk_blocking(packages.clone()));
+    assert_eq!(async_bytes, blocking_bytes, "both walks must agree");
+
+    let parallel_bytes = runtime.block_on(walk_blocking_with(packages.clone(), parallel_size));
+    assert_eq!(blocking_bytes, parallel_bytes, "both walkers must agree");
+
+    let async_time = time(|| runtime.block_on(walk_async(packages.clone())));
+    let blocking_time = time(|| runtime.block_on(walk_blocking(packages.clone())));
+    let parallel_time =
+        time(|| runtime.block_on(walk_blocking_with(packages.clone(), parallel_size)));
+    println!("async               : {:?} (best of {})", async_time, RUNS);
+    println!(
+        "blocking            : {:?} (best of {})",
+        blocking_time, RUNS
+    );
+    println!(
+        "blocking, parallel  : {:?} (best of {})",
+        parallel_time, RUNS
+    );
+
+    let Some(largest) = packages.iter().max_by_key(|package| entries(package)) else {
+        return;
+    };
+    println!(
+        "largest package, {} ({} entries):",
+        largest.display(),
+        entries(largest)
+    );
+    let sequential_time = time(|| sequential_size(largest));
+    let parallel_time = time(|| parallel_size(largest));
+    println!(
+        "  sequential        : {:?} (best of {})",
+        sequential_time, RUNS
+    );
+    println!(
+        "  parallel          : {:?} (best of {})",
+        parallel_time, RUNS
+    );
+}
+
+fn default_dir() -> PathBuf {
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env::var_os("HOME").unwrap()).join(".cargo"));
+    // The first registry index is enough
+    std::fs::read_dir(cargo_home.join("registry/src"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path()
+}
+
+fn packages(dir: &Path) -> Vec<PathBuf> {
+    let mut packages: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    packages.sort();
+    packages
+}
+
+fn time(mut run: impl FnMut() -> u64) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+async fn walk_async(packages: Vec<PathBuf>) -> u64 {
+    let mut join_set = JoinSet::new();
+    for package in packages {
+        join_set.spawn(async move {
+            let mut bytes = 0;
+            for entry in ignore::WalkBuilder::new(&package).build() {
+                let entry = entry.unwrap();
+                if entry.file_type().unwrap().is_file() {
+                    bytes += tokio::fs::metadata(entry.path()).await.unwrap().len();
+                }
+            }
+            bytes
+        });
+    }
+    join_set.join_all().await.into_iter().sum()
+}
+
+async fn walk_blocking(packages: Vec<PathBuf>) -> u64 {
+    walk_blocking_with(packages, sequential_size).await
+}
+
+async fn walk_blocking_with(packages: Vec<PathBuf>, size: fn(&Path) -> u64) -> u64 {
+    let mut join_set = JoinSet::new();
+    for package in packages {
+        join_set.spawn_blocking(move || size(&package));
+    }
+    join_set.join_all().await.into_iter().sum()
+}
+
+fn sequential_size(package: &Path) -> u64 {
+    let mut bytes = 0;
+    for entry in ignore::WalkBuilder::new(package).build() {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_file() {
+            bytes += entry.metadata().unwrap().len();
+        }
+    }
+    bytes
+}
+
+fn parallel_size(package: &Path) -> u64 {
+    let bytes = AtomicU64::new(0);
+    ignore::WalkBuilder::new(package).build_parallel().run(|| {
+        Box::new(|entry| {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_file() {
+                bytes.fetch_add(entry.metadata().unwrap().len(), Ordering::Relaxed);
+            }
+            ignore::WalkState::Continue
+        })
+    });
+    bytes.into_inner()
+}
+
+fn entries(package: &Path) -> usize {
+    ignore::WalkBuilder::new(package).build().count()
+}
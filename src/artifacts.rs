@@ -0,0 +1,158 @@
+use crate::saturating;
+use cargo::core::{Package, PackageId};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Where the sources of each package live, to tell which package a build
+/// was made from.
+#[derive(Debug, Default)]
+pub struct PackageRoots {
+    paths: HashMap<PathBuf, PackageId>,
+    /// Registry packages by their `name-version` directory, since a different
+    /// cargo version may have unpacked them under another index directory.
+    registry: HashMap<OsString, PackageId>,
+}
+
+impl PackageRoots {
+    pub fn new<'a>(packages: impl IntoIterator<Item = &'a Package>) -> Self {
+        let mut roots = PackageRoots::default();
+        for package in packages {
+            let package_id = package.package_id();
+            roots.paths.insert(package.root().to_path_buf(), package_id);
+            if let (true, Some(dir_name)) = (
+                package_id.source_id().is_registry(),
+                package.root().file_name(),
+            ) {
+                roots.registry.insert(dir_name.to_os_string(), package_id);
+            }
+        }
+        roots
+    }
+
+    /// The package rooted at `dir`.
+    fn get(&self, dir: &Path) -> Option<PackageId> {
+        if let Some(package_id) = self.paths.get(dir) {
+            return Some(*package_id);
+        }
+        // `.../registry/src/<index>/<name-version>`
+        let registry_src = dir.parent()?.parent()?;
+        if registry_src.ends_with("registry/src") {
+            return self.registry.get(dir.file_name()?).copied();
+        }
+        None
+    }
+}
+
+/// The combined size of the compiled artifacts (`.rlib`, `.rmeta` and shared
+/// libraries for proc-macros) of each package, across every build found in
+/// `target_dir`.
+///
+/// Artifact file names only carry the crate name and a hash, so they are
+/// matched to packages through the dep-info (`.d`) file rustc writes next to
+/// them, which lists the source files that went into the build. Packages
+/// that were never built are missing from the result.
+pub fn artifact_sizes(target_dir: &Path, roots: &PackageRoots) -> HashMap<PackageId, u64> {
+    let mut sizes = HashMap::new();
+    for deps_dir in deps_dirs(target_dir) {
+        let Ok(entries) = std::fs::read_dir(&deps_dir) else {
+            continue;
+        };
+        // `libfoo-1a2b3c.rlib` and `foo-1a2b3c.d` share the stem `foo-1a2b3c`
+        let mut artifacts: HashMap<String, u64> = HashMap::new();
+        let mut dep_infos = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if path.extension().is_some_and(|extension| extension == "d") {
+                dep_infos.push((stem.to_string(), path.clone()));
+            } else if let Ok(metadata) = entry.metadata() {
+                let stem = stem.strip_prefix("lib").unwrap_or(stem);
+                saturating::add(
+                    artifacts.entry(stem.to_string()).or_default(),
+                    metadata.len(),
+                );
+            }
+        }
+
+        for (stem, dep_info) in dep_infos {
+            let Some(bytes) = artifacts.get(&stem) else {
+                continue;
+            };
+            let Ok(contents) = std::fs::read_to_string(&dep_info) else {
+                continue;
+            };
+            if let Some(package_id) = built_package(&contents, roots) {
+                saturating::add(sizes.entry(package_id).or_default(), *bytes);
+            }
+        }
+    }
+    sizes
+}
+
+/// The `deps` directories of every profile, for the host and for each
+/// `--target` triple.
+fn deps_dirs(target_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let Ok(entries) = std::fs::read_dir(target_dir) else {
+        return dirs;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.join("deps").is_dir() {
+            dirs.push(path.join("deps"));
+        } else if let Ok(profiles) = std::fs::read_dir(&path) {
+            dirs.extend(
+                profiles
+                    .flatten()
+                    .map(|profile| profile.path().join("deps"))
+                    .filter(|deps| deps.is_dir()),
+            );
+        }
+    }
+    dirs
+}
+
+/// The package whose sources a dep-info file lists, taking the innermost
+/// package root when one package is nested in another.
+fn built_package(dep_info: &str, roots: &PackageRoots) -> Option<PackageId> {
+    dep_info
+        .lines()
+        // `target: source source ...`, the first rule is enough
+        .find_map(|line| line.split_once(": ").map(|(_, sources)| sources))?
+        .split_whitespace()
+        .find_map(|source| {
+            Path::new(source)
+                .ancestors()
+                .skip(1)
+                .find_map(|dir| roots.get(dir))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo::core::SourceId;
+
+    #[test]
+    fn test_built_package_picks_innermost_root() {
+        let source = SourceId::for_path(Path::new("/work")).unwrap();
+        let outer = PackageId::try_new("outer", "1.0.0", source).unwrap();
+        let inner = PackageId::try_new("inner", "1.0.0", source).unwrap();
+        let roots = PackageRoots {
+            paths: HashMap::from([
+                (PathBuf::from("/work"), outer),
+                (PathBuf::from("/work/inner"), inner),
+            ]),
+            ..Default::default()
+        };
+
+        let dep_info = "/work/target/debug/deps/inner-1a2b.d: /work/inner/src/lib.rs\n\n\
+                        /work/inner/src/lib.rs:\n";
+        assert_eq!(built_package(dep_info, &roots), Some(inner));
+        let dep_info = "/t/deps/other-3c4d.d: /elsewhere/src/lib.rs\n";
+        assert_eq!(built_package(dep_info, &roots), None);
+    }
+}
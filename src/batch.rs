@@ -0,0 +1,180 @@
+use crate::cli::DepsizeArgs;
+use crate::report::{format_size, pad};
+use crate::saturating;
+use anyhow::{bail, Context, Result};
+use cargo::core::Workspace;
+use cargo::GlobalContext;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// One workspace in the `--output-dir` summary.
+#[derive(Debug, Serialize)]
+struct WorkspaceSummary {
+    name: String,
+    manifest_path: PathBuf,
+    /// Where its full report was written.
+    report: PathBuf,
+    package_count: usize,
+    total_bytes: u64,
+}
+
+/// `summary.json` in the `--output-dir`.
+#[derive(Debug, Serialize)]
+struct BatchSummary {
+    workspaces: Vec<WorkspaceSummary>,
+    /// Summed over the workspaces, so a package used by several of them is
+    /// counted once per workspace.
+    package_count: usize,
+    total_bytes: u64,
+}
+
+/// Measures each workspace separately, writing its JSON report to
+/// `output_dir/<name>.json`, then writes `output_dir/summary.json` and prints
+/// the total of each workspace.
+///
+/// A workspace is named after its root directory relative to `cwd`, such as
+/// `services-api` for `services/api`. The gates apply to each workspace on
+/// its own, and the violations of all of them are reported at the end.
+pub async fn analyze_all(
+    gctx: &GlobalContext,
+    args: &DepsizeArgs,
+    manifest_paths: &[PathBuf],
+    output_dir: &Path,
+    cwd: &Path,
+) -> Result<()> {
+    std::fs::create_dir_all(output_dir)
+        .with_context(|| format!("failed to create {}", output_dir.display()))?;
+
+    // `summary` is taken by the summary itself
+    let mut names = HashSet::from(["summary".to_string()]);
+    let mut workspaces = Vec::new();
+    let mut violations = Vec::new();
+    let mut interrupted = false;
+    for manifest_path in manifest_paths {
+        let workspace = Workspace::new(manifest_path, gctx)?;
+        let name = unique_name(workspace_name(workspace.root(), cwd), &mut names);
+        let (report, workspace_violations) = crate::analyze(&workspace, args)
+            .await
+            .with_context(|| format!("failed to measure {}", manifest_path.display()))?;
+
+        let report_path = output_dir.join(format!("{}.json", name));
+        std::fs::write(&report_path, serde_json::to_string_pretty(&report)? + "\n")
+            .with_context(|| format!("failed to write {}", report_path.display()))?;
+        violations.extend(
+            workspace_violations
+                .into_iter()
+                .map(|violation| format!("{}: {}", name, violation)),
+        );
+        workspaces.push(WorkspaceSummary {
+            name,
+            manifest_path: manifest_path.clone(),
+            report: report_path,
+            package_count: report.package_count,
+            total_bytes: report.total_bytes,
+        });
+        if report.interrupted {
+            // The remaining workspaces are skipped along with the rest of this one
+            interrupted = true;
+            break;
+        }
+    }
+
+    let summary = BatchSummary {
+        package_count: workspaces.iter().map(|w| w.package_count).sum(),
+        total_bytes: saturating::sum(workspaces.iter().map(|w| w.total_bytes)),
+        workspaces,
+    };
+    let summary_path = output_dir.join("summary.json");
+    std::fs::write(
+        &summary_path,
+        serde_json::to_string_pretty(&summary)? + "\n",
+    )
+    .with_context(|| format!("failed to write {}", summary_path.display()))?;
+
+    if args.json && args.pretty {
+        outln!("{}", serde_json::to_string_pretty(&summary)?);
+    } else if args.json {
+        outln!("{}", serde_json::to_string(&summary)?);
+    } else {
+        if interrupted {
+            outln!("(interrupted, partial results)");
+        }
+        for workspace in &summary.workspaces {
+            outln!(
+                "{} : {}  ({} dependencies)",
+                pad(&workspace.name, 25),
+                format_size(workspace.total_bytes, args.size_format()),
+                workspace.package_count
+            );
+        }
+        if !args.no_total {
+            outln!(
+                "> Total size: {} across {} workspaces",
+                format_size(summary.total_bytes, args.size_format()),
+                summary.workspaces.len()
+            );
+        }
+    }
+
+    if interrupted {
+        crate::exit(130);
+    }
+    if !violations.is_empty() {
+        bail!("{}", violations.join("\n"));
+    }
+    Ok(())
+}
+
+/// `root` relative to `cwd` with `-` between the directories, or the name of
+/// `root` itself when that's `cwd` or outside of it.
+fn workspace_name(root: &Path, cwd: &Path) -> String {
+    let relative: Vec<String> = root
+        .strip_prefix(cwd)
+        .map(|relative| {
+            relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    if relative.is_empty() {
+        root.file_name().map_or("workspace".to_string(), |name| {
+            name.to_string_lossy().into_owned()
+        })
+    } else {
+        relative.join("-")
+    }
+}
+
+/// `name`, or `name-2`, `name-3`... if it's already taken.
+fn unique_name(name: String, taken: &mut HashSet<String>) -> String {
+    let mut unique = name.clone();
+    let mut n = 1;
+    while !taken.insert(unique.clone()) {
+        n += 1;
+        unique = format!("{}-{}", name, n);
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_name() {
+        let cwd = Path::new("/repo");
+        assert_eq!(
+            workspace_name(Path::new("/repo/services/api"), cwd),
+            "services-api"
+        );
+        assert_eq!(workspace_name(Path::new("/repo"), cwd), "repo");
+        assert_eq!(workspace_name(Path::new("/elsewhere/tool"), cwd), "tool");
+
+        let mut taken = HashSet::from(["summary".to_string()]);
+        assert_eq!(unique_name("summary".to_string(), &mut taken), "summary-2");
+        assert_eq!(unique_name("api".to_string(), &mut taken), "api");
+        assert_eq!(unique_name("api".to_string(), &mut taken), "api-2");
+    }
+}
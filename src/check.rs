@@ -0,0 +1,241 @@
+use crate::cli::DepsizeArgs;
+use crate::compare::ChangeStatus;
+use crate::report::{format_size, PackageReport, Report};
+use anyhow::{Context, Result};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The crate names given with `--deny-crate` and in the `--deny-file`.
+///
+/// The file lists one name per line; blank lines and `#` comments are
+/// ignored.
+pub fn denied_crates(args: &DepsizeArgs) -> Result<HashSet<String>> {
+    let mut names: HashSet<String> = args.deny_crate.iter().cloned().collect();
+    if let Some(path) = &args.deny_file {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read deny file {}", path.display()))?;
+        names.extend(
+            contents
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string),
+        );
+    }
+    Ok(names)
+}
+
+/// The approved crates of an `--allowlist` file.
+#[derive(Debug, Default)]
+pub struct Allowlist {
+    /// Approved at any version.
+    names: HashSet<String>,
+    /// Approved only at that version, from `name@version` entries.
+    versions: HashSet<(String, String)>,
+}
+
+impl Allowlist {
+    /// Reads one `name` or `name@version` entry per line, ignoring blank lines
+    /// and `#` comments like the `--deny-file`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read allowlist {}", path.display()))?;
+        Ok(Allowlist::parse(&contents))
+    }
+
+    fn parse(contents: &str) -> Self {
+        let mut allowlist = Allowlist::default();
+        for entry in contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|entry| !entry.is_empty())
+        {
+            match entry.split_once('@') {
+                Some((name, version)) => {
+                    allowlist
+                        .versions
+                        .insert((name.trim().to_string(), version.trim().to_string()));
+                }
+                None => {
+                    allowlist.names.insert(entry.to_string());
+                }
+            }
+        }
+        allowlist
+    }
+
+    pub fn allows(&self, name: &str, version: &str) -> bool {
+        self.names.contains(name)
+            || self
+                .versions
+                .contains(&(name.to_string(), version.to_string()))
+    }
+}
+
+/// Describes every measured package larger than `--max-package-size`, and
+/// with `--deny-yanked` every yanked one. With `--allowlist` the measured
+/// packages are the unapproved ones, and each of them is a violation too.
+///
+/// Takes all measured packages rather than the listed ones, so hiding or
+/// folding packages can't get them past the gate.
+pub fn package_violations(packages: &[PackageReport], args: &DepsizeArgs) -> Vec<String> {
+    let mut violations = Vec::new();
+    if let Some(max_bytes) = args.max_package_size {
+        violations.extend(
+            packages
+                .iter()
+                .filter(|package| package.bytes > max_bytes)
+                .map(|package| {
+                    format!(
+                        "{} is {}, more than the allowed {}",
+                        package.name_ver(),
+                        format_size(package.bytes, args.size_format()),
+                        format_size(max_bytes, args.size_format())
+                    )
+                }),
+        );
+    }
+    if args.deny_yanked {
+        violations.extend(
+            packages
+                .iter()
+                .filter(|package| package.yanked)
+                .map(|package| format!("{} v{} is yanked", package.name, package.version)),
+        );
+    }
+    if args.allowlist.is_some() {
+        // The heaviest first, they're the ones most worth reviewing
+        let mut unapproved: Vec<&PackageReport> = packages.iter().collect();
+        unapproved.sort_by(|a, b| {
+            (Reverse(a.bytes), &a.name, &a.version).cmp(&(Reverse(b.bytes), &b.name, &b.version))
+        });
+        violations.extend(unapproved.into_iter().map(|package| {
+            format!(
+                "{} v{} is not on the allowlist, it takes {}",
+                package.name,
+                package.version,
+                format_size(package.bytes, args.size_format())
+            )
+        }));
+    }
+    violations
+}
+
+/// Describes every path dependency found by `--verify-no-path-deps`,
+/// heaviest first.
+pub fn path_dependency_violations(packages: &[PackageReport], args: &DepsizeArgs) -> Vec<String> {
+    let mut packages: Vec<&PackageReport> = packages.iter().collect();
+    packages.sort_by(|a, b| (Reverse(a.bytes), &a.name).cmp(&(Reverse(b.bytes), &b.name)));
+    packages
+        .into_iter()
+        .map(|package| {
+            format!(
+                "{} v{} is a path dependency at {}, it takes {}",
+                package.name,
+                package.version,
+                package.root.display(),
+                format_size(package.bytes, args.size_format())
+            )
+        })
+        .collect()
+}
+
+/// Describes how the report fails the denylist, `--max-total-size` and
+/// `--sum-threshold-percent`.
+pub fn report_violations(report: &Report, args: &DepsizeArgs) -> Vec<String> {
+    let mut violations: Vec<String> = report
+        .denied
+        .iter()
+        .map(|package| {
+            format!(
+                "{} is denied, it takes {}",
+                package.name_ver(),
+                format_size(package.bytes, args.size_format())
+            )
+        })
+        .collect();
+
+    if let Some(max_bytes) = args.max_total_size {
+        if report.total_bytes > max_bytes {
+            violations.push(format!(
+                "total size is {}, more than the allowed {}",
+                format_size(report.total_bytes, args.size_format()),
+                format_size(max_bytes, args.size_format())
+            ));
+        }
+    }
+
+    if let (Some(comparison), Some(max_pct)) = (&report.comparison, args.sum_threshold_percent) {
+        let change_pct = comparison.total_change_percent();
+        if change_pct > max_pct {
+            violations.push(format!(
+                "total size grew by {:.2}% ({} -> {} bytes), more than the allowed {}%",
+                change_pct, comparison.old_total_bytes, comparison.new_total_bytes, max_pct
+            ));
+        }
+    }
+
+    if let Some(comparison) = report.comparison.as_ref().filter(|_| args.fail_on_removed) {
+        violations.extend(
+            comparison
+                .changes
+                .iter()
+                .filter(|change| change.status == ChangeStatus::Removed)
+                .map(|change| {
+                    format!(
+                        "{} v{} was removed, it took {}",
+                        change.name,
+                        change.old_version.as_deref().unwrap_or_default(),
+                        format_size(change.old_bytes.unwrap_or(0), args.size_format())
+                    )
+                }),
+        );
+    }
+
+    violations
+}
+
+/// Whether any of the gates checked above was configured.
+pub fn has_gates(args: &DepsizeArgs) -> bool {
+    args.max_package_size.is_some()
+        || args.max_total_size.is_some()
+        || !args.deny_crate.is_empty()
+        || args.deny_file.is_some()
+        || args.deny_yanked
+        || args.allowlist.is_some()
+        || args.sum_threshold_percent.is_some()
+        || args.fail_on_removed
+        || args.verify_no_path_deps
+}
+
+/// Prints the `--check` verdict: `PASS`, or `FAIL` followed by one line per
+/// violation.
+pub fn print_verdict(report: &Report, violations: &[String], args: &DepsizeArgs) {
+    if violations.is_empty() {
+        outln!(
+            "PASS: {} dependencies, {}",
+            report.package_count,
+            format_size(report.total_bytes, args.size_format())
+        );
+    } else {
+        outln!("FAIL: {} violations", violations.len());
+        for violation in violations {
+            outln!("  - {}", violation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist_entries() {
+        let allowlist = Allowlist::parse("# approved\nserde\nitoa@1.0.11  # pinned\n\n");
+        assert!(allowlist.allows("serde", "1.0.0"));
+        assert!(allowlist.allows("itoa", "1.0.11"));
+        assert!(!allowlist.allows("itoa", "1.0.10"));
+        assert!(!allowlist.allows("memchr", "2.7.4"));
+    }
+}
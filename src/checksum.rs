@@ -0,0 +1,145 @@
+use anyhow::{Context, Result};
+use cargo_util::Sha256;
+use flate2::read::GzDecoder;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tokio::task::JoinSet;
+
+/// A registry package to check against the checksum in `Cargo.lock`.
+pub struct Expected {
+    /// `name vX.Y.Z`, for the warnings.
+    pub label: String,
+    /// The unpacked sources, `$CARGO_HOME/registry/src/<index>/<name>-<version>`.
+    pub root: PathBuf,
+    /// The SHA-256 `Cargo.lock` records for the `.crate` archive.
+    pub checksum: String,
+}
+
+/// Checks every package's `.crate` archive against its `Cargo.lock`
+/// checksum, then the unpacked sources against the archive, and warns about
+/// every package that doesn't match.
+///
+/// The checksum covers the archive rather than the unpacked files, so both
+/// steps are needed to tell whether the measured sources are what was
+/// published. Returns how many packages failed.
+pub async fn verify_checksums(packages: Vec<Expected>) -> Result<usize> {
+    let mut join_set = JoinSet::new();
+    for package in packages {
+        join_set.spawn_blocking(move || {
+            let problem = verify(&package.root, &package.checksum)
+                .with_context(|| format!("failed to verify {}", package.label))?;
+            anyhow::Ok(problem.map(|problem| (package.label, problem)))
+        });
+    }
+
+    let mut mismatches = Vec::new();
+    while let Some(res) = join_set.join_next().await {
+        if let Some(mismatch) = res?? {
+            mismatches.push(mismatch);
+        }
+    }
+    mismatches.sort();
+    for (label, problem) in &mismatches {
+        eprintln!("warning: {} does not match Cargo.lock: {}", label, problem);
+    }
+    Ok(mismatches.len())
+}
+
+/// Describes the first way the package at `root` differs from what
+/// `checksum` vouches for, if any.
+fn verify(root: &Path, checksum: &str) -> Result<Option<String>> {
+    let Some(archive) = archive_path(root) else {
+        return Ok(Some("it isn't unpacked from a registry cache".to_string()));
+    };
+    let Ok(file) = File::open(&archive) else {
+        return Ok(Some(format!("{} is missing", archive.display())));
+    };
+    let actual = Sha256::new().update_file(&file)?.finish_hex();
+    if actual != checksum {
+        return Ok(Some(format!(
+            "{} has checksum {}, expected {}",
+            archive.display(),
+            actual,
+            checksum
+        )));
+    }
+
+    // Every entry is `<name>-<version>/<path>`
+    let mut archived = HashSet::new();
+    let mut tar = tar::Archive::new(GzDecoder::new(File::open(&archive)?));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path: PathBuf = entry.path()?.components().skip(1).collect();
+        let mut expected = Vec::new();
+        entry.read_to_end(&mut expected)?;
+        match std::fs::read(root.join(&path)) {
+            Ok(contents) if contents == expected => {}
+            Ok(_) => return Ok(Some(format!("{} was modified", path.display()))),
+            Err(_) => return Ok(Some(format!("{} is missing", path.display()))),
+        }
+        archived.insert(path);
+    }
+
+    for path in files(root, root)? {
+        // Written by cargo once the archive is unpacked
+        if !archived.contains(&path) && path != Path::new(".cargo-ok") {
+            return Ok(Some(format!("{} was added", path.display())));
+        }
+    }
+    Ok(None)
+}
+
+/// `registry/src/<index>/<name>-<version>` is unpacked from
+/// `registry/cache/<index>/<name>-<version>.crate`.
+pub fn archive_path(root: &Path) -> Option<PathBuf> {
+    let dir_name = root.file_name()?.to_str()?;
+    let index = root.parent()?;
+    let registry_src = index.parent()?;
+    if !registry_src.ends_with("registry/src") {
+        return None;
+    }
+    Some(
+        registry_src
+            .parent()?
+            .join("cache")
+            .join(index.file_name()?)
+            .join(format!("{}.crate", dir_name)),
+    )
+}
+
+/// Every file below `dir`, relative to `root`.
+fn files(root: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            found.extend(files(root, &entry.path())?);
+        } else {
+            found.push(entry.path().strip_prefix(root)?.to_path_buf());
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_path_mirrors_the_registry_layout() {
+        assert_eq!(
+            archive_path(Path::new(
+                "/home/.cargo/registry/src/index-1a2b/serde-1.0.0"
+            )),
+            Some(PathBuf::from(
+                "/home/.cargo/registry/cache/index-1a2b/serde-1.0.0.crate"
+            ))
+        );
+        assert_eq!(archive_path(Path::new("/work/vendor/serde-1.0.0")), None);
+    }
+}
@@ -0,0 +1,1021 @@
+use crate::report::SizeFormat;
+use crate::report_template::ReportTemplate;
+use crate::template::Template;
+use cargo::core::dependency::DepKind;
+use cargo::core::{Dependency, Package};
+use clap::{Args, Parser, ValueEnum};
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+/// Cargo invokes external subcommands as `cargo-depsize depsize <args>`,
+/// so the real arguments live under the `depsize` subcommand.
+#[derive(Debug, Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+pub enum CargoCli {
+    Depsize(DepsizeArgs),
+}
+
+/// Calculates and displays the total size of each dependency in your Rust project.
+#[derive(Debug, Args)]
+#[command(version, about)]
+pub struct DepsizeArgs {
+    /// Path to the `Cargo.toml` of the project to measure, or its directory;
+    /// with `--output-dir`, several may be given
+    #[arg(long, value_name = "PATH", num_args = 1..)]
+    pub manifest_path: Vec<PathBuf>,
+
+    /// Measure each `--manifest-path` on its own, write its JSON report to
+    /// DIR/<workspace>.json and print the total of each, with a summary of
+    /// all of them in DIR/summary.json
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["registry_cache", "lock", "explain", "tree", "find_duplicative_content", "check", "format", "toml", "total_bytes", "compare"])]
+    pub output_dir: Option<PathBuf>,
+
+    /// Assert that `Cargo.lock` will remain unchanged
+    #[arg(long)]
+    pub locked: bool,
+
+    /// Run without accessing the network
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Equivalent to specifying both --locked and --offline
+    #[arg(long)]
+    pub frozen: bool,
+
+    /// Start from a preset combination of flags; flags given as well take
+    /// precedence over the preset's, and its flags that conflict with them
+    /// are dropped
+    #[arg(
+        long,
+        env = "DEPSIZE_CONFIG_PROFILE",
+        value_name = "PROFILE",
+        value_enum
+    )]
+    pub config_profile: Option<ConfigProfile>,
+
+    /// Measure every crate in the cargo registry cache instead of a project
+    #[arg(long)]
+    pub registry_cache: bool,
+
+    /// Print the report as TOML, one `[[dependency]]` table per package
+    #[arg(long, conflicts_with = "json")]
+    pub toml: bool,
+
+    /// Don't print the `> Total size:` line
+    #[arg(long)]
+    pub no_total: bool,
+
+    /// Print nothing but the total size in bytes
+    #[arg(long, conflicts_with_all = ["json", "toml"])]
+    pub total_bytes: bool,
+
+    /// Print the JSON Schema of the `--json` report and exit
+    #[arg(long)]
+    pub report_schema: bool,
+
+    /// Show which packages pull in CRATE, up to the workspace, with their sizes
+    #[arg(long, value_name = "CRATE", conflicts_with_all = ["registry_cache", "lock", "json", "toml", "total_bytes"])]
+    pub explain: Option<String>,
+
+    /// Print the dependency tree of each workspace member with the size of
+    /// every package and of everything below it
+    #[arg(long, conflicts_with_all = ["registry_cache", "lock", "explain", "json", "toml", "total_bytes"])]
+    pub tree: bool,
+
+    /// How to order the children of each node in `--tree`
+    #[arg(long, value_name = "KEY", value_enum, default_value_t = TreeSort::Size, requires = "tree")]
+    pub tree_sort: TreeSort,
+
+    /// Hash every file of every package and list packages whose content
+    /// mostly overlaps, such as forks or duplicated vendored sources (slow)
+    #[arg(long, conflicts_with_all = ["registry_cache", "lock", "explain", "json", "toml", "total_bytes"])]
+    pub find_duplicative_content: bool,
+
+    /// Measure the packages pinned by a standalone `Cargo.lock` instead of a project
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["registry_cache", "manifest_path", "baseline_git_ref"])]
+    pub lock: Option<PathBuf>,
+
+    /// Output format; `ndjson-stream` prints a JSON line per listed package
+    /// as soon as it's measured, with its file count and scan time, then one
+    /// with the total
+    #[arg(long, env = "DEPSIZE_FORMAT", value_enum, value_name = "FORMAT", conflicts_with_all = ["json", "toml", "total_bytes", "check", "explain", "find_duplicative_content", "baseline"])]
+    pub format: Option<OutputFormat>,
+
+    /// Print the report as JSON instead of a table
+    #[arg(long, env = "DEPSIZE_JSON")]
+    pub json: bool,
+
+    /// Indent the `--json` output by two spaces instead of printing it on a
+    /// single line
+    #[arg(long, requires = "json")]
+    pub pretty: bool,
+
+    /// Also copy the output, in whichever format, to the clipboard
+    ///
+    /// Uses pbcopy on macOS, clip on Windows and wl-copy, xclip or xsel
+    /// elsewhere; without any of them the output is only printed. The
+    /// printed output keeps its colors, the copy is plain text.
+    #[arg(long)]
+    pub clipboard: bool,
+
+    /// Show a link to each package's crates.io page or source repository
+    #[arg(long)]
+    pub with_urls: bool,
+
+    /// How to order the listing
+    #[arg(long, env = "DEPSIZE_SORT", value_name = "KEY", value_enum, default_value_t = SortKey::Size)]
+    pub sort: SortKey,
+
+    /// Override the order of `--sort`: ascending or descending
+    ///
+    /// Defaults to ascending, except for `files`, which lists most first.
+    #[arg(
+        long,
+        env = "DEPSIZE_SORT_DIRECTION",
+        value_name = "DIRECTION",
+        value_enum
+    )]
+    pub sort_direction: Option<SortDirection>,
+
+    /// How to order packages that tie on `--sort`, in that key's default
+    /// direction
+    #[arg(long, value_name = "KEY", value_enum, default_value_t = SortKey::Name)]
+    pub sort_secondary: SortKey,
+
+    /// How sizes are rounded to two decimals in the human-readable output
+    #[arg(long, env = "DEPSIZE_ROUNDING", value_name = "MODE", value_enum, default_value_t = Rounding::Nearest)]
+    pub rounding: Rounding,
+
+    /// Never show sizes in a unit smaller than UNIT
+    #[arg(
+        long,
+        env = "DEPSIZE_UNITS",
+        value_name = "UNIT",
+        value_enum,
+        ignore_case = true
+    )]
+    pub min_unit: Option<Unit>,
+
+    /// When to color the listing by size
+    #[arg(long, env = "DEPSIZE_COLOR", value_name = "WHEN", value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Show how many packages have been measured on stderr, even when it
+    /// isn't a terminal
+    #[arg(long, conflicts_with = "no_progress")]
+    pub progress: bool,
+
+    /// Never show the progress line, even on a terminal
+    #[arg(long, env = "DEPSIZE_NO_PROGRESS")]
+    pub no_progress: bool,
+
+    /// Color packages at least this large yellow, e.g. `1MB` or `500KB`
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "1MB")]
+    pub warn_size: u64,
+
+    /// Color packages at least this large red, e.g. `5MB`
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "5MB")]
+    pub error_size: u64,
+
+    /// Print a shields.io endpoint badge of the total size instead of the
+    /// report, e.g. `{"schemaVersion":1,"label":"deps","message":"48.30MB","color":"orange"}`
+    #[arg(long, conflicts_with_all = ["json", "toml", "total_bytes", "check", "format", "output_template_file"])]
+    pub badge: bool,
+
+    /// Make the `--badge` orange from this total size on
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "50MB", requires = "badge")]
+    pub badge_warn_size: u64,
+
+    /// Make the `--badge` red from this total size on
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "200MB", requires = "badge")]
+    pub badge_error_size: u64,
+
+    /// Also print how many dependencies are larger than SIZE, e.g. `500KB`
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub summary_only_deps_over: Option<u64>,
+
+    /// Also show the size of each package's compiled `.rlib`, `.rmeta` and
+    /// proc-macro libraries, across every build in the target directory
+    #[arg(long, conflicts_with_all = ["registry_cache", "lock"])]
+    pub with_artifacts: bool,
+
+    /// With `--with-artifacts`, also show each package's source size as a
+    /// percentage of its compiled artifacts
+    #[arg(long, requires = "with_artifacts")]
+    pub as_percentage_of_binary: bool,
+
+    /// What each dependency's size and the total include: the dependency
+    /// alone, or everything below it too, with shared packages counted once
+    /// per dependency (`transitive`) or once in the total (`unique`)
+    #[arg(long, env = "DEPSIZE_SUM_BY", value_name = "MODEL", value_enum, default_value_t = SumBy::Direct, conflicts_with_all = ["registry_cache", "lock"])]
+    pub sum_by: SumBy,
+
+    /// Experimental: build the workspace from scratch with `cargo build
+    /// --timings` and show how long each package took to compile
+    #[arg(long, conflicts_with_all = ["registry_cache", "lock"])]
+    pub compile_time: bool,
+
+    /// Experimental: estimate how long a cold `cargo fetch` and unpack of
+    /// each package would take, from its `.crate` size and file count
+    #[arg(long, conflicts_with_all = ["registry_cache", "lock"])]
+    pub estimate_install_time: bool,
+
+    /// The download bandwidth `--estimate-install-time` assumes, in megabits
+    /// per second
+    #[arg(long, value_name = "MBPS", default_value_t = 100.0, requires = "estimate_install_time", value_parser = parse_positive)]
+    pub bandwidth: f64,
+
+    /// With `--compile-time`, read the timings from this existing
+    /// `cargo-timing.html` report instead of building
+    #[arg(long, value_name = "PATH", requires = "compile_time")]
+    pub timings_report: Option<PathBuf>,
+
+    /// Also list dev and build dependencies, once each, annotated with every
+    /// kind of dependency they are, e.g. `[normal, dev]`
+    #[arg(long, conflicts_with_all = ["registry_cache", "lock"])]
+    pub dedupe_kinds: bool,
+
+    /// Leave out dependencies that are only of KIND; repeat for several
+    /// kinds. The others kinds are all listed, as with `--dedupe-kinds`
+    #[arg(long, env = "DEPSIZE_EXCLUDE_KIND", value_delimiter = ',', value_name = "KIND", value_enum, conflicts_with_all = ["registry_cache", "lock"])]
+    pub exclude_kind: Vec<DependencyKind>,
+
+    /// Count each package's cached `.crate` archive as well as its unpacked
+    /// sources, the disk space removing it would free
+    #[arg(long)]
+    pub disk_impact: bool,
+
+    /// Show the directory each package was measured in
+    #[arg(long)]
+    pub show_path: bool,
+
+    /// Leave out the files that differ between caches of the same crate
+    /// version, `.cargo-ok` and `.cargo_vcs_info.json`, so it measures the
+    /// same on every machine
+    #[arg(long)]
+    pub canonical: bool,
+
+    /// Width of the name column in the human-readable output, 0 to fit the
+    /// longest name
+    #[arg(long, value_name = "N", default_value_t = 25)]
+    pub name_width: usize,
+
+    /// Show versions without their prerelease and build metadata, such as
+    /// `1.0.0` for `1.0.0-alpha.3+build.99`; JSON and TOML keep the full version
+    #[arg(long)]
+    pub strip_prerelease: bool,
+
+    /// Comma-separated list of columns to show, e.g. `name,version,size,files,percent,source`
+    #[arg(
+        long,
+        env = "DEPSIZE_COLUMNS",
+        value_name = "LIST",
+        value_enum,
+        value_delimiter = ','
+    )]
+    pub columns: Option<Vec<Column>>,
+
+    /// Compare against a report previously saved with `--json`
+    #[arg(
+        long,
+        env = "DEPSIZE_COMPARE",
+        value_name = "REPORT",
+        group = "baseline"
+    )]
+    pub compare: Option<PathBuf>,
+
+    /// Compare against the dependencies of the project at a git revision
+    #[arg(
+        long,
+        env = "DEPSIZE_BASELINE_GIT_REF",
+        value_name = "REF",
+        group = "baseline"
+    )]
+    pub baseline_git_ref: Option<String>,
+
+    /// Record the size of every file of each package in the report, so a
+    /// `--json` report saved with it can be used for `--diff-files`
+    #[arg(long)]
+    pub record_files: bool,
+
+    /// When comparing, only list the packages that were added, removed or
+    /// changed, with a count of the unchanged ones
+    #[arg(long, requires = "baseline")]
+    pub relative_change_only: bool,
+
+    /// When comparing, list the files of CRATE that were added, removed or
+    /// changed size instead of the packages; the baseline report must have
+    /// been saved with `--record-files`
+    #[arg(long, value_name = "CRATE", requires = "baseline")]
+    pub diff_files: Option<String>,
+
+    /// When comparing, fail if the total size grew by more than PCT percent
+    #[arg(long, env = "DEPSIZE_THRESHOLD", value_name = "PCT", requires = "baseline", value_parser = parse_non_negative)]
+    pub sum_threshold_percent: Option<f64>,
+
+    /// When comparing, fail if a package of the baseline is no longer
+    /// resolved, listing each one
+    #[arg(long, requires = "baseline")]
+    pub fail_on_removed: bool,
+
+    /// Fail if the total size of the dependencies is more than SIZE, e.g. `50MB`
+    #[arg(long, env = "DEPSIZE_MAX_TOTAL_SIZE", value_name = "SIZE", value_parser = parse_size)]
+    pub max_total_size: Option<u64>,
+
+    /// Fail if any single dependency is larger than SIZE, e.g. `5MB`
+    #[arg(long, env = "DEPSIZE_MAX_PACKAGE_SIZE", value_name = "SIZE", value_parser = parse_size)]
+    pub max_package_size: Option<u64>,
+
+    /// Fail if CRATE is anywhere in the resolved dependency graph, may be
+    /// given several times
+    #[arg(
+        long,
+        env = "DEPSIZE_DENY_CRATE",
+        value_delimiter = ',',
+        value_name = "CRATE",
+        conflicts_with = "registry_cache"
+    )]
+    pub deny_crate: Vec<String>,
+
+    /// Like `--deny-crate` for every crate listed in PATH, one name per line
+    #[arg(long, value_name = "PATH", conflicts_with = "registry_cache")]
+    pub deny_file: Option<PathBuf>,
+
+    /// List only the resolved crates, direct or not, that aren't approved in
+    /// PATH (one `name` or `name@version` per line), and fail if there are any
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["registry_cache", "lock", "only_duplicates"])]
+    pub allowlist: Option<PathBuf>,
+
+    /// Fail if any measured package's version has been yanked from its registry
+    #[arg(long, env = "DEPSIZE_DENY_YANKED", conflicts_with = "registry_cache")]
+    pub deny_yanked: bool,
+
+    /// Fail if any dependency comes from a local path rather than a
+    /// registry or git, other than the workspace's own members, listing
+    /// each with its size
+    #[arg(long, conflicts_with_all = ["registry_cache", "lock"])]
+    pub verify_no_path_deps: bool,
+
+    /// Run the configured gates and print a PASS/FAIL verdict with the
+    /// violations instead of the report
+    ///
+    /// Exits with status 1 if any gate fails.
+    #[arg(long, env = "DEPSIZE_CHECK", conflicts_with_all = ["json", "toml", "total_bytes", "explain", "find_duplicative_content"])]
+    pub check: bool,
+
+    /// Render each package with a template such as `{name}={bytes}`
+    ///
+    /// Placeholders: {name}, {version}, {size}, {bytes}, {percent}.
+    #[arg(long, env = "DEPSIZE_FORMAT_TEMPLATE", value_name = "TEMPLATE", value_parser = Template::parse, conflicts_with = "columns")]
+    pub format_template: Option<Template>,
+
+    /// Render the whole report with the template in PATH instead of the usual
+    /// output
+    ///
+    /// The template sees the `--json` report: `{total_bytes | size}`,
+    /// `{{ for package in packages }}{package.name}{{ endfor }}`,
+    /// `{{ if comparison }}…{{ else }}…{{ endif }}`.
+    #[arg(long, value_name = "PATH", value_parser = ReportTemplate::load, conflicts_with_all = ["json", "toml", "total_bytes", "check", "format_template", "columns"])]
+    pub output_template_file: Option<ReportTemplate>,
+
+    /// Also write the total, package count and top 5 packages as JSON to PATH
+    /// [default: target/depsize-summary.json]
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    pub summary_json: Option<Option<PathBuf>>,
+
+    /// After each successful run, append its total and dependency count, with
+    /// the time and git commit, to the CSV file PATH
+    /// [default: depsize-history.csv]
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    pub log_history: Option<Option<PathBuf>>,
+
+    /// Overwrite files given on the command line without asking
+    ///
+    /// Without it, overwriting an existing file asks for confirmation when
+    /// run in a terminal.
+    #[arg(long, short = 'y')]
+    pub assume_yes: bool,
+
+    /// Take registry package sizes from an index instead of walking them
+    ///
+    /// The index is a `--json` report or a `{"name@version": bytes}` map, read
+    /// from a file or an http(s) URL. Packages missing from it are walked.
+    #[arg(long, env = "DEPSIZE_SIZE_INDEX", value_name = "PATH|URL")]
+    pub size_index: Option<String>,
+
+    /// Resolve the dependencies that would come from crates.io from the
+    /// registry NAME configured under `[registries]` in `.cargo/config.toml`
+    /// instead, such as a private mirror
+    ///
+    /// Their sources are measured where that registry unpacks them. The
+    /// dependencies naming a registry in their manifest are unaffected.
+    #[arg(long, value_name = "NAME", conflicts_with = "registry_cache")]
+    pub registry: Option<String>,
+
+    /// Resolve features for the target TRIPLE instead of the host. The
+    /// dependencies of every platform are still measured, unless
+    /// `--strict-target` is given too
+    #[arg(long, value_name = "TRIPLE", conflicts_with_all = ["registry_cache", "lock"])]
+    pub target: Option<String>,
+
+    /// With `--target`, only measure the dependencies whose `cfg` matches it,
+    /// and list the declared ones it leaves out
+    #[arg(long, requires = "target")]
+    pub strict_target: bool,
+
+    /// Leave the build dependencies of CRATE, and everything they pull in, out
+    /// of the listing and the total
+    #[arg(long, value_name = "CRATE")]
+    pub ignore_build_deps_of: Option<String>,
+
+    /// Hide packages that make up less than PCT percent of the total size
+    ///
+    /// Hidden packages still count towards the total.
+    #[arg(long, env = "DEPSIZE_MIN_CONTRIBUTION", value_name = "PCT", value_parser = parse_percent)]
+    pub min_contribution: Option<f64>,
+
+    /// Only list dependencies whose crate is of this type
+    #[arg(long, value_name = "TYPE", value_enum, conflicts_with_all = ["registry_cache", "lock"])]
+    pub package_type: Option<PackageType>,
+
+    /// Combine the packages smaller than SIZE into a single `(N smaller
+    /// crates)` row, e.g. `100KB`
+    #[arg(long, env = "DEPSIZE_COLLAPSE_UNDER", value_name = "SIZE", value_parser = parse_size)]
+    pub collapse_under: Option<u64>,
+
+    /// Print at most N package rows, then one line with the count and size
+    /// of the rest. The total still counts every package
+    #[arg(long, value_name = "N")]
+    pub max_output_lines: Option<usize>,
+
+    /// Show how many packages fall into each size range instead of listing them
+    #[arg(long)]
+    pub histogram: bool,
+
+    /// Show how much of each package, and of the whole tree, is the
+    /// `Cargo.toml`, `Cargo.toml.orig`, `.cargo_vcs_info.json` and README
+    /// every crate ships regardless of its code
+    #[arg(long)]
+    pub metadata_overhead: bool,
+
+    /// Also print the median, 90th and 99th percentile and mean package size
+    #[arg(long)]
+    pub percentiles: bool,
+
+    /// Show a subtotal per value of a manifest field instead of listing packages
+    #[arg(long, env = "DEPSIZE_GROUP_BY", value_name = "FIELD", value_enum)]
+    pub group_by: Option<GroupBy>,
+
+    /// With `--group-by author`, whether a package with several authors counts
+    /// towards the first one or each of them
+    #[arg(long, value_name = "WHO", value_enum, default_value_t = AuthorAttribution::First)]
+    pub author_attribution: AuthorAttribution,
+
+    /// List only crates resolved at more than one version, with the versions
+    /// of each name grouped together. Unlike the default listing this
+    /// includes transitive dependencies
+    #[arg(long, conflicts_with = "histogram")]
+    pub only_duplicates: bool,
+
+    /// Split each package's size into src/, tests/, examples/, benches/ and other
+    #[arg(long)]
+    pub breakdown_dirs: bool,
+
+    /// Also measure the project's own package and show it as a `[self]` row
+    #[arg(long)]
+    pub with_self: bool,
+
+    /// Also list the N largest files across all listed packages
+    #[arg(long, env = "DEPSIZE_TOP_FILES", value_name = "N")]
+    pub top_files: Option<usize>,
+
+    /// Show file paths relative to their package [default]
+    #[arg(long, overrides_with = "absolute_paths")]
+    pub relative_paths: bool,
+
+    /// Show file paths in full, e.g. to open them
+    #[arg(long, overrides_with = "relative_paths")]
+    pub absolute_paths: bool,
+
+    /// Combine all proc-macro crates into a single `[proc-macros]` row
+    #[arg(long)]
+    pub aggregate_proc_macros: bool,
+
+    /// Don't descend more than N directories deep into a package
+    #[arg(long, env = "DEPSIZE_MAX_DEPTH_FILES", value_name = "N")]
+    pub max_depth_files: Option<usize>,
+
+    /// Walk every package with several threads, rather than only those with
+    /// more entries than a small crate has
+    #[arg(long)]
+    pub parallel_walk: bool,
+
+    /// Walk every package with a single thread, however large
+    #[arg(long, conflicts_with = "parallel_walk")]
+    pub no_parallel_walk: bool,
+
+    /// Only read the size of this fraction (0 to 1) of each package's files
+    /// and extrapolate the rest, for a quick estimate
+    #[arg(long, value_name = "FLOAT", value_parser = parse_sample_rate, conflicts_with = "record_files")]
+    pub sample_rate: Option<f64>,
+
+    /// The seed picking the files `--sample-rate` reads, so that runs with
+    /// the same seed sample the same files
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "sample_rate")]
+    pub sample_seed: u64,
+
+    /// How many threads the parallel walk walks a package with, instead of
+    /// picking from the number of CPUs
+    #[arg(long, value_name = "N", conflicts_with = "no_parallel_walk")]
+    pub walk_threads: Option<usize>,
+
+    /// Fail if a package's sources are missing from the cache or unreadable,
+    /// instead of counting them as empty
+    #[arg(long)]
+    pub fail_if_missing_cache: bool,
+
+    /// Check each registry package's cached `.crate` archive against the
+    /// checksum in `Cargo.lock`, and its unpacked sources against the
+    /// archive, warning about every mismatch
+    #[arg(long, conflicts_with = "registry_cache")]
+    pub verify_checksums: bool,
+
+    /// How packages are scanned concurrently
+    #[arg(long, value_name = "MODEL", value_enum, default_value_t = ConcurrencyModel::Blocking)]
+    pub concurrency_model: ConcurrencyModel,
+
+    /// What to do when a file can't be measured
+    #[arg(long, env = "DEPSIZE_ON_ERROR", value_name = "POLICY", value_enum, default_value_t = ErrorPolicy::Skip)]
+    pub on_error: ErrorPolicy,
+}
+
+/// Scan strategies for `--concurrency-model`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConcurrencyModel {
+    /// One tokio task per package, reading file metadata with `tokio::fs`;
+    /// the parallel walk doesn't apply
+    Tasks,
+    /// One blocking-pool thread per package, reading metadata with `std::fs`,
+    /// and `ignore`'s parallel walker for large packages (faster on typical
+    /// filesystems)
+    Blocking,
+}
+
+/// Formats for `--format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per line, emitted as each package finishes
+    NdjsonStream,
+}
+
+/// A column of the human-readable table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    /// Package name
+    Name,
+    /// Package version
+    Version,
+    /// Total size
+    Size,
+    /// Number of files
+    Files,
+    /// Share of the total size
+    Percent,
+    /// Where the package comes from (crates.io, registry name, git URL or path)
+    Source,
+}
+
+/// The order of the package listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// By total size, smallest first
+    Size,
+    /// By crate name, alphabetically
+    Name,
+    /// By version, oldest first
+    Version,
+    /// By number of files, most first
+    Files,
+}
+
+impl SortKey {
+    /// The direction used without `--sort-direction`.
+    pub fn default_direction(self) -> SortDirection {
+        match self {
+            // Most files first, these are the ones that slow filesystem-heavy work down
+            SortKey::Files => SortDirection::Desc,
+            // Smallest first, so the biggest end up right above the total
+            SortKey::Size | SortKey::Name | SortKey::Version => SortDirection::Asc,
+        }
+    }
+}
+
+/// What each listed dependency's size and the total add up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SumBy {
+    /// Each dependency on its own, added up
+    Direct,
+    /// Each dependency with everything it pulls in, added up, so packages
+    /// shared by several dependencies count once for each
+    Transitive,
+    /// Each dependency with everything it pulls in, but a total that counts
+    /// every package once
+    Unique,
+}
+
+impl SumBy {
+    /// How the model is named in the output.
+    pub fn label(self) -> &'static str {
+        match self {
+            SumBy::Direct => "direct",
+            SumBy::Transitive => "transitive",
+            SumBy::Unique => "unique",
+        }
+    }
+}
+
+/// The order of the children of each node in `--tree`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TreeSort {
+    /// By the size of the package and everything below it, biggest first
+    Size,
+    /// By crate name, alphabetically
+    Name,
+}
+
+/// Which way `--sort` orders the listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+/// How a size is rounded to the two decimals shown next to its unit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Rounding {
+    /// To the nearest value
+    #[default]
+    Nearest,
+    /// Always up, so sizes are never under-reported
+    Up,
+    /// Always down
+    Down,
+}
+
+impl Rounding {
+    /// Rounds `value` to two decimals. `Nearest` is left to the formatter.
+    pub fn apply(self, value: f64) -> f64 {
+        match self {
+            Rounding::Nearest => value,
+            Rounding::Up => (value * 100.0).ceil() / 100.0,
+            Rounding::Down => (value * 100.0).floor() / 100.0,
+        }
+    }
+}
+
+/// Whether colored output is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// When writing to a terminal and `NO_COLOR` isn't set
+    Auto,
+    /// Always
+    Always,
+    /// Never
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color the output now, given where stdout goes.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// A unit sizes are shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Unit {
+    /// Kilobytes (1024 bytes)
+    Kb,
+    /// Megabytes (1024 KB)
+    Mb,
+}
+
+/// The manifest field `--group-by` groups packages by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// The `license` field (or `license-file`)
+    License,
+    /// The `edition` field
+    Edition,
+    /// The `authors` field, without email addresses
+    Author,
+    /// The `repository` field, so crates published from one repository
+    /// add up
+    Repository,
+}
+
+/// Which authors a package with several counts towards in `--group-by author`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AuthorAttribution {
+    /// Only the first one listed
+    First,
+    /// Every one of them, so the subtotals add up to more than the total
+    Each,
+}
+
+impl GroupBy {
+    /// The manifest field as written in `Cargo.toml`.
+    pub fn field_name(self) -> &'static str {
+        match self {
+            GroupBy::License => "license",
+            GroupBy::Edition => "edition",
+            GroupBy::Author => "authors",
+            GroupBy::Repository => "repository",
+        }
+    }
+}
+
+/// The kinds of dependency `--exclude-kind` leaves out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DependencyKind {
+    /// `[dependencies]`
+    Normal,
+    /// `[dev-dependencies]`
+    Dev,
+    /// `[build-dependencies]`
+    Build,
+}
+
+impl DependencyKind {
+    fn dep_kind(self) -> DepKind {
+        match self {
+            DependencyKind::Normal => DepKind::Normal,
+            DependencyKind::Dev => DepKind::Development,
+            DependencyKind::Build => DepKind::Build,
+        }
+    }
+}
+
+/// The kinds of crate `--package-type` selects, judged by the package's
+/// targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PackageType {
+    /// Has a library target that isn't a proc-macro
+    Lib,
+    /// Only has binary targets, no library
+    Bin,
+    /// Has a proc-macro target
+    ProcMacro,
+}
+
+impl PackageType {
+    pub fn matches(self, package: &Package) -> bool {
+        let targets = package.targets();
+        match self {
+            PackageType::Lib => targets
+                .iter()
+                .any(|target| target.is_lib() && !target.proc_macro()),
+            PackageType::Bin => {
+                targets.iter().any(|target| target.is_bin())
+                    && !targets.iter().any(|target| target.is_lib())
+            }
+            PackageType::ProcMacro => targets.iter().any(|target| target.proc_macro()),
+        }
+    }
+}
+
+/// A `--config-profile` preset, see [`crate::profile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigProfile {
+    /// Only the duplicated crates, with their sources and a subtotal per
+    /// license
+    Audit,
+    /// JSON without progress or color, failing on yanked versions and, when
+    /// comparing, on more than 5% growth
+    Ci,
+    /// The whole registry cache, counting the archives as well
+    Disk,
+    /// Only the total in bytes
+    Minimal,
+}
+
+/// How measurement errors during the walk are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorPolicy {
+    /// Log the error and keep going, producing a best-effort report
+    Skip,
+    /// Stop at the first error and exit with a non-zero status
+    Fail,
+}
+
+impl ErrorPolicy {
+    /// Logs the error under `Skip`, hands it back under `Fail`.
+    pub fn handle(self, err: anyhow::Error) -> anyhow::Result<()> {
+        match self {
+            ErrorPolicy::Skip => {
+                eprintln!("Error: {:#}", err);
+                Ok(())
+            }
+            ErrorPolicy::Fail => Err(err),
+        }
+    }
+}
+
+impl DepsizeArgs {
+    /// How sizes are shown in the human-readable output.
+    pub fn size_format(&self) -> SizeFormat {
+        SizeFormat {
+            rounding: self.rounding,
+            min_unit: self.min_unit,
+        }
+    }
+
+    /// Whether to show the progress line: with `--progress`, or when stderr
+    /// is a terminal unless `--no-progress` is given (or NDJSON is streamed).
+    pub fn shows_progress(&self) -> bool {
+        if self.progress || self.no_progress {
+            return self.progress;
+        }
+        std::io::stderr().is_terminal() && self.format != Some(OutputFormat::NdjsonStream)
+    }
+
+    /// Whether every file of each package has to be recorded, for
+    /// `--record-files` or `--diff-files`.
+    pub fn records_files(&self) -> bool {
+        self.record_files || self.diff_files.is_some()
+    }
+
+    /// Whether dependencies of `kind` are listed: normal ones by default,
+    /// every kind with `--dedupe-kinds` or `--exclude-kind`, minus the
+    /// excluded ones.
+    pub fn lists_kind(&self, kind: DepKind) -> bool {
+        let listed = kind == DepKind::Normal || self.dedupe_kinds || !self.exclude_kind.is_empty();
+        listed
+            && !self
+                .exclude_kind
+                .iter()
+                .any(|excluded| excluded.dep_kind() == kind)
+    }
+
+    /// Whether a dependency edge declared as `dependencies` survives
+    /// `--exclude-kind`, that is whether one of them is of a kind that isn't
+    /// excluded.
+    pub fn keeps_edge(&self, dependencies: &HashSet<Dependency>) -> bool {
+        dependencies.iter().any(|dependency| {
+            !self
+                .exclude_kind
+                .iter()
+                .any(|excluded| excluded.dep_kind() == dependency.kind())
+        })
+    }
+
+    /// Returns the name of the strictest network/lockfile mode in effect, if any.
+    pub fn cache_only_mode(&self) -> Option<&'static str> {
+        if self.frozen {
+            Some("--frozen")
+        } else if self.offline {
+            Some("--offline")
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a percentage between 0 and 100, e.g. `1` or `2.5`.
+fn parse_percent(s: &str) -> Result<f64, String> {
+    let pct: f64 = s
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("`{s}` is not a number"))?;
+    if (0.0..=100.0).contains(&pct) {
+        Ok(pct)
+    } else {
+        Err(format!("`{s}` is not between 0 and 100"))
+    }
+}
+
+/// Parses a non-negative number, e.g. `5` or `0.5`.
+fn parse_non_negative(s: &str) -> Result<f64, String> {
+    let value: f64 = s
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("`{s}` is not a number"))?;
+    if value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("`{s}` is negative"))
+    }
+}
+
+/// Parses a number above zero, e.g. `100` or `12.5`.
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| format!("`{s}` is not a number"))?;
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("`{s}` is not above 0"))
+    }
+}
+
+/// Parses a fraction above 0 and at most 1, e.g. `0.1`.
+fn parse_sample_rate(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| format!("`{s}` is not a number"))?;
+    if value > 0.0 && value <= 1.0 {
+        Ok(value)
+    } else {
+        Err(format!("`{s}` is not between 0 and 1"))
+    }
+}
+
+/// Parses a size such as `1048576`, `500KB` or `1.5MB` into bytes.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let upper = s.trim().to_ascii_uppercase();
+    let (number, unit) = match upper.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => upper.split_at(i),
+        None => (upper.as_str(), "B"),
+    };
+    let multiplier = match unit.trim() {
+        "B" => 1,
+        "KB" => 1024,
+        "MB" => 1024 * 1024,
+        "GB" => 1024 * 1024 * 1024,
+        _ => return Err(format!("`{s}` has an unknown unit, use B, KB, MB or GB")),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{s}` is not a size"))?;
+    if number.is_nan() || number < 0.0 {
+        return Err(format!("`{s}` is negative or not a number"));
+    }
+    let bytes = number * multiplier as f64;
+    // Also catches a number with too many digits, which parses as infinity
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("`{s}` is too large"));
+    }
+    Ok(bytes as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_rejects_what_is_not_a_size() {
+        assert_eq!(parse_size("1.5KB"), Ok(1536));
+        assert_eq!(parse_size("0"), Ok(0));
+        let infinite = "9".repeat(400);
+        for size in ["nan", "NaNMB", "inf", "-1MB", infinite.as_str(), "1e30"] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+    }
+
+    #[test]
+    fn test_options_are_read_from_the_environment() {
+        // Variables set here would leak into the tests parsing arguments
+        // alongside, so the parsing runs in a child running only this test
+        if std::env::var_os("DEPSIZE_TEST_CHILD").is_none() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args([
+                    "--exact",
+                    "cli::tests::test_options_are_read_from_the_environment",
+                ])
+                .env("DEPSIZE_TEST_CHILD", "1")
+                .env("DEPSIZE_FORMAT", "ndjson-stream")
+                .env("DEPSIZE_FORMAT_TEMPLATE", "{name}")
+                .env("DEPSIZE_GROUP_BY", "license")
+                .env("DEPSIZE_EXCLUDE_KIND", "dev,build")
+                .env("DEPSIZE_MAX_DEPTH_FILES", "3")
+                .env("DEPSIZE_MAX_TOTAL_SIZE", "1MB")
+                .env("DEPSIZE_NO_PROGRESS", "true")
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+
+        let CargoCli::Depsize(args) = CargoCli::parse_from(["cargo", "depsize"]);
+        assert_eq!(args.format, Some(OutputFormat::NdjsonStream));
+        assert!(args.format_template.is_some());
+        assert_eq!(args.group_by, Some(GroupBy::License));
+        assert_eq!(
+            args.exclude_kind,
+            [DependencyKind::Dev, DependencyKind::Build]
+        );
+        assert_eq!(args.max_depth_files, Some(3));
+        assert_eq!(args.max_total_size, Some(1024 * 1024));
+        assert!(args.no_progress);
+
+        // Flags given on the command line win
+        let CargoCli::Depsize(args) =
+            CargoCli::parse_from(["cargo", "depsize", "--group-by", "edition"]);
+        assert_eq!(args.group_by, Some(GroupBy::Edition));
+    }
+}
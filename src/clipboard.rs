@@ -0,0 +1,91 @@
+use crate::cli::DepsizeArgs;
+use crate::output;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The clipboard tools tried in order, each with its arguments for reading
+/// the text to copy from stdin.
+#[cfg(target_os = "macos")]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[&["pbcopy"]];
+#[cfg(windows)]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[&["clip"]];
+#[cfg(not(any(target_os = "macos", windows)))]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+/// With `--clipboard`, starts keeping what's printed for [`copy_output`].
+pub fn start(args: &DepsizeArgs) {
+    if args.clipboard {
+        output::capture();
+    }
+}
+
+/// Copies everything printed since [`start`] to the clipboard, without its
+/// colors, warning when no clipboard tool takes it.
+pub fn copy_output() {
+    let Some(text) = output::take_captured() else {
+        return;
+    };
+    if !text.is_empty() && !copy(strip_colors(&text).as_bytes()) {
+        let tools: Vec<&str> = CLIPBOARD_COMMANDS
+            .iter()
+            .map(|command| command[0])
+            .collect();
+        eprintln!(
+            "warning: no clipboard available (tried {}), the report was only printed",
+            tools.join(", ")
+        );
+    }
+}
+
+/// `text` without its ANSI escape sequences.
+fn strip_colors(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Up to and including the sequence's final byte, such as the `m` of
+            // a color
+            chars.by_ref().skip(1).find(|c| ('@'..='~').contains(c));
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Hands `text` to the first clipboard tool that takes it.
+fn copy(text: &[u8]) -> bool {
+    CLIPBOARD_COMMANDS.iter().any(|command| {
+        let Ok(mut child) = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            return false;
+        };
+        let written = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text).is_ok());
+        child.wait().is_ok_and(|status| status.success()) && written
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_colors() {
+        assert_eq!(
+            strip_colors("\x1b[31mserde\x1b[0m : 1.00KB\n"),
+            "serde : 1.00KB\n"
+        );
+    }
+}
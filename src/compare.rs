@@ -0,0 +1,516 @@
+use crate::report::{format_size, pad, PackageReport, SizeFormat};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// A previously saved `--json` report to compare against. Only the fields
+/// needed for the comparison are read, so older and newer reports both load.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Baseline {
+    pub packages: Vec<BaselinePackage>,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BaselinePackage {
+    pub name: String,
+    pub version: String,
+    pub bytes: u64,
+    /// Only in reports saved with `--record-files`.
+    #[serde(default)]
+    pub file_sizes: Option<BTreeMap<String, u64>>,
+}
+
+impl Baseline {
+    /// Uses freshly measured packages as the baseline, with `total_bytes`
+    /// summed the way the report it's compared with sums its own.
+    pub fn from_packages(packages: &[PackageReport], total_bytes: u64) -> Self {
+        Baseline {
+            packages: packages
+                .iter()
+                .map(|package| BaselinePackage {
+                    name: package.name.clone(),
+                    version: package.version.clone(),
+                    bytes: package.bytes,
+                    file_sizes: package.file_sizes.clone(),
+                })
+                .collect(),
+            total_bytes,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read baseline {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("{} is not a `cargo depsize --json` report", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeStatus {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+/// How one package differs between the baseline and the current run.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageChange {
+    pub name: String,
+    pub status: ChangeStatus,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub old_bytes: Option<u64>,
+    pub new_bytes: Option<u64>,
+}
+
+impl PackageChange {
+    /// Growth in bytes, negative when the package shrank or was removed.
+    pub fn delta(&self) -> i128 {
+        self.new_bytes.unwrap_or(0) as i128 - self.old_bytes.unwrap_or(0) as i128
+    }
+}
+
+/// The difference between a baseline report and the current one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub old_total_bytes: u64,
+    pub new_total_bytes: u64,
+    pub changes: Vec<PackageChange>,
+}
+
+impl Comparison {
+    /// Pairs up the packages of both reports by name. When a name occurs once
+    /// on each side a version bump counts as a change; otherwise versions are
+    /// matched exactly and leftovers are added or removed.
+    /// Compares the baseline with every measured package, including those the
+    /// report folds or hides, totalling `total_bytes`.
+    pub fn new(baseline: &Baseline, packages: &[PackageReport], total_bytes: u64) -> Self {
+        let mut old_by_name = BTreeMap::<&str, Vec<&BaselinePackage>>::new();
+        for package in &baseline.packages {
+            old_by_name.entry(&package.name).or_default().push(package);
+        }
+        let mut new_by_name = BTreeMap::<&str, Vec<&PackageReport>>::new();
+        for package in packages {
+            new_by_name.entry(&package.name).or_default().push(package);
+        }
+
+        let mut names: Vec<&str> = old_by_name
+            .keys()
+            .chain(new_by_name.keys())
+            .copied()
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let mut changes = Vec::new();
+        for name in names {
+            let mut old = old_by_name.remove(name).unwrap_or_default();
+            let mut new = new_by_name.remove(name).unwrap_or_default();
+
+            if old.len() == 1 && new.len() == 1 {
+                changes.push(change(name, Some(old[0]), Some(new[0])));
+                continue;
+            }
+
+            new.retain(|new_package| {
+                match old
+                    .iter()
+                    .position(|old| old.version == new_package.version)
+                {
+                    Some(i) => {
+                        changes.push(change(name, Some(old.remove(i)), Some(new_package)));
+                        false
+                    }
+                    None => true,
+                }
+            });
+            changes.extend(old.into_iter().map(|old| change(name, Some(old), None)));
+            changes.extend(new.into_iter().map(|new| change(name, None, Some(new))));
+        }
+
+        Comparison {
+            old_total_bytes: baseline.total_bytes,
+            new_total_bytes: total_bytes,
+            changes,
+        }
+    }
+
+    pub fn total_delta(&self) -> i128 {
+        self.new_total_bytes as i128 - self.old_total_bytes as i128
+    }
+
+    /// Growth of the total relative to the baseline, in percent.
+    pub fn total_change_percent(&self) -> f64 {
+        if self.old_total_bytes == 0 {
+            if self.new_total_bytes == 0 {
+                0.0
+            } else {
+                f64::INFINITY
+            }
+        } else {
+            self.total_delta() as f64 / self.old_total_bytes as f64 * 100.0
+        }
+    }
+
+    /// Prints one line per package, marked `+` added, `-` removed, `~`
+    /// changed, followed by the old and new totals. With `changes_only` the
+    /// unchanged packages are left out and only counted.
+    pub fn print_human(&self, size_format: SizeFormat, changes_only: bool) {
+        let mut hidden = 0;
+        for change in &self.changes {
+            if changes_only && change.status == ChangeStatus::Unchanged {
+                hidden += 1;
+                continue;
+            }
+            let (marker, versions, sizes) = match change.status {
+                ChangeStatus::Added => (
+                    '+',
+                    format!("v{}", change.new_version.as_deref().unwrap_or_default()),
+                    format_size(change.new_bytes.unwrap_or(0), size_format),
+                ),
+                ChangeStatus::Removed => (
+                    '-',
+                    format!("v{}", change.old_version.as_deref().unwrap_or_default()),
+                    format_size(change.old_bytes.unwrap_or(0), size_format),
+                ),
+                ChangeStatus::Changed | ChangeStatus::Unchanged => {
+                    let old_version = change.old_version.as_deref().unwrap_or_default();
+                    let new_version = change.new_version.as_deref().unwrap_or_default();
+                    let versions = if old_version == new_version {
+                        format!("v{}", new_version)
+                    } else {
+                        format!("v{} -> v{}", old_version, new_version)
+                    };
+                    let sizes = if change.status == ChangeStatus::Unchanged {
+                        format_size(change.new_bytes.unwrap_or(0), size_format)
+                    } else {
+                        format!(
+                            "{} -> {} ({})",
+                            format_size(change.old_bytes.unwrap_or(0), size_format),
+                            format_size(change.new_bytes.unwrap_or(0), size_format),
+                            format_delta(change.delta(), size_format)
+                        )
+                    };
+                    let marker = if change.status == ChangeStatus::Changed {
+                        '~'
+                    } else {
+                        ' '
+                    };
+                    (marker, versions, sizes)
+                }
+            };
+            let name_ver = format!("{} ({})", change.name, versions);
+            outln!("{} {} : {}", marker, pad(&name_ver, 23), sizes);
+        }
+        if hidden > 0 {
+            outln!(
+                "({} unchanged {} hidden)",
+                hidden,
+                if hidden == 1 {
+                    "dependency"
+                } else {
+                    "dependencies"
+                }
+            );
+        }
+
+        outln!(
+            "> Old total size: {}",
+            format_size(self.old_total_bytes, size_format)
+        );
+        outln!(
+            "> New total size: {}",
+            format_size(self.new_total_bytes, size_format)
+        );
+        outln!(
+            "> Change: {} ({:+.2}%)",
+            format_delta(self.total_delta(), size_format),
+            self.total_change_percent()
+        );
+    }
+}
+
+/// How one file of a package differs between the baseline and the current run.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    pub status: ChangeStatus,
+    pub old_bytes: Option<u64>,
+    pub new_bytes: Option<u64>,
+}
+
+impl FileChange {
+    /// Growth in bytes, negative when the file shrank or was removed.
+    pub fn delta(&self) -> i128 {
+        self.new_bytes.unwrap_or(0) as i128 - self.old_bytes.unwrap_or(0) as i128
+    }
+}
+
+/// The files of one package that differ from the baseline, for `--diff-files`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    pub name: String,
+    pub old_version: String,
+    pub new_version: String,
+    pub old_bytes: u64,
+    pub new_bytes: u64,
+    /// Only the files that changed, biggest growth first.
+    pub changes: Vec<FileChange>,
+}
+
+impl FileDiff {
+    /// Compares the files of the package `name` in both reports, which must
+    /// each list exactly one version of it.
+    pub fn new(baseline: &Baseline, packages: &[PackageReport], name: &str) -> Result<Self> {
+        let old = match baseline
+            .packages
+            .iter()
+            .filter(|package| package.name == name)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [] => bail!("`{}` is not in the baseline", name),
+            [old] => *old,
+            _ => bail!("the baseline has several versions of `{}`", name),
+        };
+        let new = match packages
+            .iter()
+            .filter(|package| package.name == name)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [] => bail!("`{}` is not in the report", name),
+            [new] => *new,
+            _ => bail!("the report has several versions of `{}`", name),
+        };
+        let Some(old_files) = &old.file_sizes else {
+            bail!(
+                "the baseline has no file sizes for `{}`, save it with `--json --record-files`",
+                name
+            );
+        };
+        let empty = BTreeMap::new();
+        let new_files = new.file_sizes.as_ref().unwrap_or(&empty);
+
+        let mut changes: Vec<FileChange> = old_files
+            .keys()
+            .chain(new_files.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|path| {
+                let old_bytes = old_files.get(path).copied();
+                let new_bytes = new_files.get(path).copied();
+                let status = match (old_bytes, new_bytes) {
+                    (None, _) => ChangeStatus::Added,
+                    (_, None) => ChangeStatus::Removed,
+                    (old_bytes, new_bytes) if old_bytes == new_bytes => return None,
+                    _ => ChangeStatus::Changed,
+                };
+                Some(FileChange {
+                    path: path.clone(),
+                    status,
+                    old_bytes,
+                    new_bytes,
+                })
+            })
+            .collect();
+        // Stable, so equal deltas stay in path order
+        changes.sort_by_key(|change| std::cmp::Reverse(change.delta()));
+
+        Ok(FileDiff {
+            name: name.to_string(),
+            old_version: old.version.clone(),
+            new_version: new.version.clone(),
+            old_bytes: old.bytes,
+            new_bytes: new.bytes,
+            changes,
+        })
+    }
+
+    /// Prints one line per file, marked `+` added, `-` removed, `~` changed,
+    /// followed by the old and new size of the package.
+    pub fn print_human(&self, size_format: SizeFormat) {
+        for change in &self.changes {
+            let (marker, sizes) = match change.status {
+                ChangeStatus::Added => {
+                    ('+', format_size(change.new_bytes.unwrap_or(0), size_format))
+                }
+                ChangeStatus::Removed => {
+                    ('-', format_size(change.old_bytes.unwrap_or(0), size_format))
+                }
+                ChangeStatus::Changed | ChangeStatus::Unchanged => (
+                    '~',
+                    format!(
+                        "{} -> {} ({})",
+                        format_size(change.old_bytes.unwrap_or(0), size_format),
+                        format_size(change.new_bytes.unwrap_or(0), size_format),
+                        format_delta(change.delta(), size_format)
+                    ),
+                ),
+            };
+            outln!("{} {} : {}", marker, pad(&change.path, 23), sizes);
+        }
+
+        let versions = if self.old_version == self.new_version {
+            format!("v{}", self.new_version)
+        } else {
+            format!("v{} -> v{}", self.old_version, self.new_version)
+        };
+        outln!(
+            "> {} ({}): {} -> {} ({})",
+            self.name,
+            versions,
+            format_size(self.old_bytes, size_format),
+            format_size(self.new_bytes, size_format),
+            format_delta(self.new_bytes as i128 - self.old_bytes as i128, size_format)
+        );
+    }
+}
+
+fn change(name: &str, old: Option<&BaselinePackage>, new: Option<&PackageReport>) -> PackageChange {
+    let status = match (old, new) {
+        (None, _) => ChangeStatus::Added,
+        (_, None) => ChangeStatus::Removed,
+        (Some(old), Some(new)) if old.bytes == new.bytes && old.version == new.version => {
+            ChangeStatus::Unchanged
+        }
+        _ => ChangeStatus::Changed,
+    };
+
+    PackageChange {
+        name: name.to_string(),
+        status,
+        old_version: old.map(|old| old.version.clone()),
+        new_version: new.map(|new| new.version.clone()),
+        old_bytes: old.map(|old| old.bytes),
+        new_bytes: new.map(|new| new.bytes),
+    }
+}
+
+/// Formats a signed byte difference, e.g. `+1.00KB (1024 bytes)`.
+pub fn format_delta(delta: i128, size_format: SizeFormat) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!(
+        "{}{}",
+        sign,
+        format_size(delta.unsigned_abs() as u64, size_format)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Report;
+
+    fn old(name: &str, version: &str, bytes: u64) -> BaselinePackage {
+        BaselinePackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            bytes,
+            file_sizes: None,
+        }
+    }
+
+    fn new(name: &str, version: &str, bytes: u64) -> PackageReport {
+        PackageReport {
+            name: name.to_string(),
+            version: version.to_string(),
+            bytes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_comparison_pairs_packages_by_name() {
+        let baseline = Baseline {
+            packages: vec![
+                old("same", "1.0.0", 10),
+                old("bumped", "1.0.0", 10),
+                old("gone", "0.1.0", 5),
+                old("dup", "1.0.0", 1),
+                old("dup", "2.0.0", 2),
+            ],
+            total_bytes: 28,
+        };
+        let report = Report {
+            packages: vec![
+                new("same", "1.0.0", 10),
+                new("bumped", "1.1.0", 30),
+                new("fresh", "0.1.0", 7),
+                new("dup", "2.0.0", 2),
+                new("dup", "3.0.0", 3),
+            ],
+            total_bytes: 52,
+            ..Default::default()
+        };
+
+        let comparison = Comparison::new(&baseline, &report.packages, report.total_bytes);
+        let statuses: Vec<(&str, ChangeStatus, i128)> = comparison
+            .changes
+            .iter()
+            .map(|change| (change.name.as_str(), change.status, change.delta()))
+            .collect();
+
+        assert_eq!(
+            statuses,
+            vec![
+                ("bumped", ChangeStatus::Changed, 20),
+                ("dup", ChangeStatus::Unchanged, 0),
+                ("dup", ChangeStatus::Removed, -1),
+                ("dup", ChangeStatus::Added, 3),
+                ("fresh", ChangeStatus::Added, 7),
+                ("gone", ChangeStatus::Removed, -5),
+                ("same", ChangeStatus::Unchanged, 0),
+            ]
+        );
+        assert_eq!(comparison.total_delta(), 24);
+    }
+
+    #[test]
+    fn test_file_diff_lists_changed_files_by_growth() {
+        let files = |files: &[(&str, u64)]| {
+            Some(
+                files
+                    .iter()
+                    .map(|(path, bytes)| (path.to_string(), *bytes))
+                    .collect(),
+            )
+        };
+        let baseline = Baseline {
+            packages: vec![BaselinePackage {
+                file_sizes: files(&[("src/lib.rs", 10), ("src/old.rs", 5), ("README", 3)]),
+                ..old("serde", "1.0.0", 18)
+            }],
+            total_bytes: 18,
+        };
+        let report = Report {
+            packages: vec![PackageReport {
+                file_sizes: files(&[("src/lib.rs", 30), ("src/new.rs", 7), ("README", 3)]),
+                ..new("serde", "1.1.0", 40)
+            }],
+            total_bytes: 40,
+            ..Default::default()
+        };
+
+        let diff = FileDiff::new(&baseline, &report.packages, "serde").unwrap();
+        let changes: Vec<(&str, ChangeStatus, i128)> = diff
+            .changes
+            .iter()
+            .map(|change| (change.path.as_str(), change.status, change.delta()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("src/lib.rs", ChangeStatus::Changed, 20),
+                ("src/new.rs", ChangeStatus::Added, 7),
+                ("src/old.rs", ChangeStatus::Removed, -5),
+            ]
+        );
+        assert!(FileDiff::new(&baseline, &report.packages, "other").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::cli::DepsizeArgs;
+use anyhow::{bail, Context, Result};
+use cargo::core::Workspace;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+/// One compilation unit in the data of a `cargo build --timings` report.
+#[derive(Debug, Deserialize)]
+struct TimingUnit {
+    name: String,
+    version: String,
+    /// Seconds, 0 for units that were already up to date.
+    duration: f64,
+}
+
+/// How long each package took to compile, in seconds by `(name, version)`,
+/// summed over its units (library, build script...).
+///
+/// Reads `--timings-report` if given. Otherwise builds the workspace from
+/// scratch with `cargo build --timings --all-features` in a temporary target
+/// directory, so that no unit is skipped for being up to date and the
+/// project's own builds are left alone, and reads the report that writes.
+pub fn compile_times(
+    workspace: &Workspace<'_>,
+    args: &DepsizeArgs,
+) -> Result<HashMap<(String, String), f64>> {
+    let html = match &args.timings_report {
+        Some(path) => read_timings_report(path)?,
+        None => {
+            let target_dir = tempfile::Builder::new()
+                .prefix("depsize-compile-time")
+                .tempdir()?;
+            build_with_timings(workspace, args, target_dir.path())?;
+            read_timings_report(&target_dir.path().join("cargo-timings/cargo-timing.html"))?
+        }
+    };
+    parse_timings(&html).context("not a cargo timings report")
+}
+
+fn read_timings_report(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Runs the build for [`compile_times`] in `target_dir`.
+fn build_with_timings(
+    workspace: &Workspace<'_>,
+    args: &DepsizeArgs,
+    target_dir: &Path,
+) -> Result<()> {
+    eprintln!(
+        "note: --compile-time is experimental, it builds the whole workspace to time it, \
+         which may take a while"
+    );
+
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut command = Command::new(cargo);
+    command
+        .args(["build", "--timings", "--workspace", "--all-features"])
+        .arg("--manifest-path")
+        .arg(workspace.root_manifest())
+        .arg("--target-dir")
+        .arg(target_dir);
+    for (given, flag) in [
+        (args.offline, "--offline"),
+        (args.locked, "--locked"),
+        (args.frozen, "--frozen"),
+    ] {
+        if given {
+            command.arg(flag);
+        }
+    }
+    let status = command.status().context("failed to run cargo build")?;
+    if !status.success() {
+        bail!("`cargo build --timings` failed, see its output above");
+    }
+    Ok(())
+}
+
+/// Sums up the `UNIT_DATA` embedded in the HTML of a timings report.
+fn parse_timings(html: &str) -> Result<HashMap<(String, String), f64>> {
+    const MARKER: &str = "const UNIT_DATA = ";
+    let Some(start) = html.find(MARKER) else {
+        bail!("no unit data found");
+    };
+    // The array is followed by the rest of the script
+    let units = serde_json::Deserializer::from_str(&html[start + MARKER.len()..])
+        .into_iter::<Vec<TimingUnit>>()
+        .next()
+        .context("no unit data found")??;
+
+    let mut durations = HashMap::new();
+    for unit in units {
+        *durations.entry((unit.name, unit.version)).or_insert(0.0) += unit.duration;
+    }
+    Ok(durations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timings_sums_units_per_package() {
+        let html = r#"<script>
+const UNIT_DATA = [
+  {"i": 0, "name": "serde", "version": "1.0.0", "mode": "run-custom-build", "target": "", "duration": 0.25},
+  {"i": 1, "name": "serde", "version": "1.0.0", "mode": "todo", "target": "", "duration": 1.5},
+  {"i": 2, "name": "itoa", "version": "1.0.11", "mode": "todo", "target": "", "duration": 0.1}
+];
+const CONCURRENCY_DATA = [];
+</script>"#;
+        let durations = parse_timings(html).unwrap();
+        assert_eq!(durations[&("serde".to_string(), "1.0.0".to_string())], 1.75);
+        assert_eq!(durations[&("itoa".to_string(), "1.0.11".to_string())], 0.1);
+        assert!(parse_timings("<html></html>").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::cli::DepsizeArgs;
+use crate::resolve::resolve_dependencies;
+use crate::size_index::measure_packages_indexed;
+use crate::tree::{print_branches, size_label};
+use crate::walk::Measured;
+use anyhow::{bail, Result};
+use cargo::core::{PackageId, Workspace};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Prints every package that `crate_name` is *used by*, recursively, up to
+/// the workspace members, like `cargo tree -i` with the size of each hop.
+pub async fn explain(
+    workspace: &Workspace<'_>,
+    args: &DepsizeArgs,
+    crate_name: &str,
+) -> Result<()> {
+    let workspace_resolve = resolve_dependencies(workspace, args)?;
+    let resolve = &workspace_resolve.targeted_resolve;
+
+    let targets: BTreeSet<PackageId> = resolve
+        .iter()
+        .filter(|id| id.name() == crate_name)
+        .collect();
+    if targets.is_empty() {
+        bail!("`{}` is not a dependency of this workspace", crate_name);
+    }
+
+    let mut dependents: HashMap<PackageId, BTreeSet<PackageId>> = HashMap::new();
+    for package_id in resolve.iter() {
+        for (dep_id, dependencies) in resolve.deps(package_id) {
+            if !args.keeps_edge(dependencies) {
+                continue;
+            }
+            dependents.entry(dep_id).or_default().insert(package_id);
+        }
+    }
+
+    // Only the packages on a path from a target to the workspace are measured
+    let mut on_path = HashSet::new();
+    let mut pending: Vec<PackageId> = targets.iter().copied().collect();
+    while let Some(package_id) = pending.pop() {
+        if on_path.insert(package_id) {
+            pending.extend(dependents.get(&package_id).into_iter().flatten());
+        }
+    }
+    let packages = workspace_resolve
+        .pkg_set
+        .packages()
+        .filter(|package| on_path.contains(&package.package_id()))
+        .map(|package| {
+            let package_id = package.package_id();
+            let label = format!("{} v{}", package_id.name(), package_id.version());
+            (package_id, label, package.root().to_path_buf())
+        })
+        .collect();
+    let Measured { sizes, interrupted } =
+        measure_packages_indexed(workspace.gctx(), args, packages, |_, _| Ok(())).await?;
+    if interrupted {
+        outln!("(interrupted, partial results)");
+    }
+
+    for (i, target) in targets.iter().enumerate() {
+        if i > 0 {
+            outln!();
+        }
+        print_branches(
+            *target,
+            &|package_id| {
+                dependents
+                    .get(&package_id)
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .collect()
+            },
+            &|package_id, _| size_label(&sizes, package_id, args),
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+/// A copy of the repository at some git revision, extracted into a temporary
+/// directory that is removed again on drop.
+pub struct Checkout {
+    _dir: TempDir,
+    manifest_path: PathBuf,
+}
+
+impl Checkout {
+    /// The copy of the workspace's root `Cargo.toml`.
+    pub fn manifest_path(&self) -> &Path {
+        &self.manifest_path
+    }
+}
+
+/// Extracts the repository containing `root_manifest` as it was at `git_ref`.
+///
+/// Uses `git archive`, so nothing in the repository itself (index, worktrees,
+/// checked-out files) is touched.
+pub fn checkout(root_manifest: &Path, git_ref: &str) -> Result<Checkout> {
+    let workspace_dir = root_manifest
+        .parent()
+        .context("the workspace manifest has no parent directory")?;
+    let toplevel = git(workspace_dir, &["rev-parse", "--show-toplevel"])?;
+    let toplevel = PathBuf::from(String::from_utf8(toplevel)?.trim_end());
+    let relative_manifest = root_manifest
+        .canonicalize()?
+        .strip_prefix(toplevel.canonicalize()?)
+        .context("the workspace manifest is outside its git repository")?
+        .to_path_buf();
+
+    let archive = git(&toplevel, &["archive", "--format=tar", git_ref])
+        .with_context(|| format!("failed to export git revision `{}`", git_ref))?;
+    let dir = tempfile::Builder::new()
+        .prefix("cargo-depsize-")
+        .tempdir()?;
+    tar::Archive::new(archive.as_slice())
+        .unpack(dir.path())
+        .with_context(|| format!("failed to extract git revision `{}`", git_ref))?;
+
+    let manifest_path = dir.path().join(&relative_manifest);
+    if !manifest_path.is_file() {
+        bail!(
+            "`{}` does not exist at git revision `{}`",
+            relative_manifest.display(),
+            git_ref
+        );
+    }
+
+    Ok(Checkout {
+        _dir: dir,
+        manifest_path,
+    })
+}
+
+/// Runs a git command in `dir` and returns its stdout.
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// The commit checked out in the repository containing `dir`, if it is one.
+pub fn head_commit(dir: &Path) -> Option<String> {
+    let head = git(dir, &["rev-parse", "HEAD"]).ok()?;
+    Some(String::from_utf8(head).ok()?.trim_end().to_string())
+}
@@ -0,0 +1,149 @@
+use crate::cli::{AuthorAttribution, GroupBy};
+use crate::report::PackageReport;
+use crate::saturating;
+use cargo::core::Package;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Bucket for packages that don't declare the grouped-by field.
+pub const UNKNOWN: &str = "<unknown>";
+
+/// The packages sharing one value of the grouped-by field.
+#[derive(Debug, Clone, Serialize)]
+pub struct Group {
+    pub key: String,
+    pub count: usize,
+    pub bytes: u64,
+    /// `name (vX.Y.Z)` of every package in the group.
+    pub packages: Vec<String>,
+}
+
+/// Packages grouped by one manifest field.
+#[derive(Debug, Clone, Serialize)]
+pub struct Grouping {
+    pub field: &'static str,
+    pub groups: Vec<Group>,
+}
+
+/// Returns the values of the grouped-by field for a package, one unless
+/// it lists several authors and they're all credited.
+pub fn group_keys(
+    package: &Package,
+    group_by: GroupBy,
+    attribution: AuthorAttribution,
+) -> Vec<String> {
+    let metadata = package.manifest().metadata();
+    let key = match group_by {
+        GroupBy::License => match (&metadata.license, &metadata.license_file) {
+            (Some(license), _) => license.clone(),
+            (None, Some(_)) => "<license file>".to_string(),
+            (None, None) => UNKNOWN.to_string(),
+        },
+        // Defaults to 2015 when not set, so every package has one
+        GroupBy::Edition => package.manifest().edition().to_string(),
+        GroupBy::Repository => match &metadata.repository {
+            Some(url) => repository_key(url),
+            None => UNKNOWN.to_string(),
+        },
+        GroupBy::Author => {
+            let mut authors: Vec<String> = metadata
+                .authors
+                .iter()
+                .map(|author| author_name(author))
+                .collect();
+            authors.dedup();
+            if authors.is_empty() {
+                return vec![UNKNOWN.to_string()];
+            }
+            if attribution == AuthorAttribution::First {
+                authors.truncate(1);
+            }
+            return authors;
+        }
+    };
+    vec![key]
+}
+
+/// `Jane Doe <jane@example.com>` without the address, which tends to change
+/// more often than the name.
+fn author_name(author: &str) -> String {
+    let name = match author.split_once('<') {
+        Some((name, _)) if !name.trim().is_empty() => name,
+        _ => author,
+    };
+    name.trim().to_string()
+}
+
+/// A `repository` URL without what tells crates of one repository apart:
+/// case, a `.git` or `/` at the end, or a `/tree/…` path into a
+/// subdirectory.
+fn repository_key(url: &str) -> String {
+    let mut key = url.trim().to_ascii_lowercase();
+    for marker in ["/tree/", "/blob/"] {
+        if let Some(start) = key.find(marker) {
+            key.truncate(start);
+        }
+    }
+    let key = key.trim_end_matches('/');
+    key.strip_suffix(".git").unwrap_or(key).to_string()
+}
+
+/// Sums the packages per group key, largest group first.
+pub fn group_packages(packages: &[PackageReport], group_by: GroupBy) -> Grouping {
+    Grouping {
+        field: group_by.field_name(),
+        groups: groups(packages, |package| {
+            package.groups.iter().map(String::as_str).collect()
+        }),
+    }
+}
+
+/// Sums the packages per name, largest first, for `--only-duplicates`.
+pub fn group_by_name(packages: &[PackageReport]) -> Vec<Group> {
+    groups(packages, |package| vec![package.name.as_str()])
+}
+
+/// Sums the packages per key, largest group first. Packages without a key
+/// are left out, and packages with several are counted in each group.
+fn groups<'a>(
+    packages: &'a [PackageReport],
+    keys: impl Fn(&'a PackageReport) -> Vec<&'a str>,
+) -> Vec<Group> {
+    let mut groups = BTreeMap::<&str, Group>::new();
+    for package in packages {
+        for key in keys(package) {
+            let group = groups.entry(key).or_insert_with(|| Group {
+                key: key.to_string(),
+                count: 0,
+                bytes: 0,
+                packages: Vec::new(),
+            });
+            group.count += 1;
+            saturating::add(&mut group.bytes, package.bytes);
+            group.packages.push(package.name_ver());
+        }
+    }
+
+    let mut groups: Vec<Group> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repository_key_matches_crates_of_one_repository() {
+        let serde = repository_key("https://github.com/serde-rs/serde");
+        for url in [
+            "https://github.com/serde-rs/serde/",
+            "https://github.com/serde-rs/serde.git",
+            "https://github.com/Serde-rs/serde/tree/master/serde_derive",
+            " https://github.com/serde-rs/serde/blob/master/README.md",
+        ] {
+            assert_eq!(repository_key(url), serde, "{}", url);
+        }
+        assert_ne!(repository_key("https://github.com/serde-rs/json"), serde);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::checksum;
+use crate::cli::{DepsizeArgs, OutputFormat};
+use crate::report::{PackageReport, StreamEntry};
+use crate::size_index::measure_packages_indexed;
+use crate::walk::Measured;
+use crate::yanked;
+use anyhow::{Context, Result};
+use cargo::core::registry::PackageRegistry;
+use cargo::core::{PackageId, SourceId};
+use cargo::sources::SourceConfigMap;
+use cargo::util::cache_lock::CacheLockMode;
+use cargo::GlobalContext;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    /// Missing for workspace members and path dependencies.
+    source: Option<String>,
+    /// Only recorded for registry packages.
+    checksum: Option<String>,
+}
+
+/// Measures every package pinned by a standalone `Cargo.lock`, downloading
+/// the ones missing from the cache unless running offline.
+///
+/// Packages without a source (workspace members and path dependencies) only
+/// exist in the project the lockfile came from and are skipped.
+///
+/// Returns the measured packages and whether the scan was interrupted.
+pub async fn measure_lockfile(
+    config: &GlobalContext,
+    args: &DepsizeArgs,
+    lockfile_path: &Path,
+) -> Result<(Vec<PackageReport>, bool)> {
+    let contents = std::fs::read_to_string(lockfile_path)
+        .with_context(|| format!("failed to read {}", lockfile_path.display()))?;
+    let lockfile: Lockfile = toml::from_str(&contents)
+        .with_context(|| format!("failed to parse {}", lockfile_path.display()))?;
+
+    let mut package_ids = Vec::new();
+    let mut local = 0;
+    for package in &lockfile.package {
+        let Some(source) = &package.source else {
+            local += 1;
+            continue;
+        };
+        let source_id = SourceId::from_url(source)
+            .with_context(|| format!("invalid source for {} v{}", package.name, package.version))?;
+        package_ids.push(PackageId::try_new(
+            package.name.as_str(),
+            package.version.as_str(),
+            source_id,
+        )?);
+    }
+    if local > 0 {
+        eprintln!(
+            "note: skipped {} workspace or path packages, their sources aren't in the lockfile",
+            local
+        );
+    }
+
+    let pkg_set = {
+        let _lock = config.acquire_package_cache_lock(CacheLockMode::DownloadExclusive)?;
+        let mut registry =
+            PackageRegistry::new_with_source_config(config, SourceConfigMap::new(config)?)?;
+        let source_ids: HashSet<SourceId> = package_ids.iter().map(|id| id.source_id()).collect();
+        registry.add_sources(source_ids)?;
+        registry.get(&package_ids)?
+    };
+    let packages = pkg_set
+        .get_many(package_ids.iter().copied())
+        .with_context(|| {
+            format!(
+                "failed to download the packages in {}",
+                lockfile_path.display()
+            )
+        })?;
+
+    if args.verify_checksums {
+        let expected = packages
+            .iter()
+            .filter_map(|package| {
+                let package_id = package.package_id();
+                let locked = lockfile.package.iter().find(|locked| {
+                    locked.name == package_id.name().as_str()
+                        && locked.version == package_id.version().to_string()
+                })?;
+                Some(checksum::Expected {
+                    label: format!("{} v{}", package_id.name(), package_id.version()),
+                    root: package.root().to_path_buf(),
+                    checksum: locked.checksum.clone()?,
+                })
+            })
+            .collect();
+        checksum::verify_checksums(expected).await?;
+    }
+
+    let yanked = yanked::yanked_packages(config, &pkg_set, package_ids.iter().copied())?;
+
+    let to_measure = packages
+        .iter()
+        .map(|package| {
+            let package_id = package.package_id();
+            let label = format!("{} v{}", package_id.name(), package_id.version());
+            (package_id, label, package.root().to_path_buf())
+        })
+        .collect();
+    let stream = args.format == Some(OutputFormat::NdjsonStream);
+    let Measured { sizes, interrupted } =
+        measure_packages_indexed(config, args, to_measure, |package_id, size| {
+            if stream {
+                StreamEntry::new(pkg_set.get_one(package_id)?, size, args).print()?;
+            }
+            Ok(())
+        })
+        .await?;
+
+    let package_infos = packages
+        .into_iter()
+        .filter_map(|package| {
+            let size = sizes.get(&package.package_id())?;
+            let mut info = PackageReport::new(package, size);
+            info.yanked = yanked.contains(&package.package_id());
+            if args.show_path {
+                info.path = Some(package.root().to_path_buf());
+            }
+            if args.breakdown_dirs {
+                info.dirs = Some(size.dirs);
+            }
+            if args.records_files() {
+                info.record_file_sizes();
+            }
+            if args.disk_impact {
+                info.add_archive_size();
+            }
+            Some(info)
+        })
+        .collect();
+
+    Ok((package_infos, interrupted))
+}
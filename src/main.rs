@@ -1,74 +1,175 @@
-use anyhow::Result;
-use cargo::core::compiler::RustcTargetData;
+#[macro_use]
+mod output;
+
+mod artifacts;
+mod batch;
+mod check;
+mod checksum;
+mod cli;
+mod clipboard;
+mod compare;
+mod compile_time;
+mod explain;
+mod git_baseline;
+mod group;
+mod history;
+mod lockfile;
+mod overlap;
+mod profile;
+mod registry_cache;
+mod report;
+mod report_template;
+mod resolve;
+mod saturating;
+mod schema;
+mod size_index;
+mod template;
+mod tree;
+mod walk;
+mod yanked;
+
+use anyhow::{anyhow, bail, Context, Result};
 use cargo::core::dependency::DepKind;
-use cargo::core::resolver::features::{CliFeatures, ForceAllTargets, HasDevUnits};
 use cargo::core::PackageId;
 use cargo::core::Workspace;
 use cargo::util::important_paths::find_root_manifest_for_wd;
 use cargo::GlobalContext;
+use cli::{DepsizeArgs, OutputFormat, SumBy};
+use compare::{Baseline, Comparison, FileDiff};
+use report::{
+    Badge, DepsOver, Estimate, GroupTotal, PackageReport, Percentiles, Report, StreamEntry,
+};
+use resolve::{
+    cut_build_dependencies, direct_dependencies, patched_packages, resolve_dependencies,
+};
 use std::collections::{HashMap, HashSet};
 use std::env;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
-use tokio::fs;
-
-use tokio::task::JoinSet;
+use walk::{measure_packages, Measured, PackageSize, WalkOptions};
 
 #[tokio::main]
 async fn main() {
-    let result = run().await;
+    let args = profile::parse_args();
+    clipboard::start(&args);
+    let result = run(&args).await;
     if let Err(err) = result {
         eprintln!("Error: {:?}", err);
-        process::exit(1);
+        exit(1);
     }
+    clipboard::copy_output();
+}
+
+/// Exits with `code`, copying the output first with `--clipboard`.
+fn exit(code: i32) -> ! {
+    clipboard::copy_output();
+    process::exit(code)
 }
 
-async fn run() -> Result<()> {
-    let config = GlobalContext::default()?;
+async fn run(args: &DepsizeArgs) -> Result<()> {
+    if args.report_schema {
+        out!("{}", schema::REPORT_SCHEMA);
+        return Ok(());
+    }
+
+    let mut config = GlobalContext::default()?;
+    config.configure(
+        0,
+        false,
+        None,
+        args.frozen,
+        args.locked,
+        args.offline,
+        &None,
+        &[],
+        &resolve::registry_overrides(args.registry.as_deref()),
+    )?;
+    if let Some(name) = &args.registry {
+        resolve::registry_source(&config, name)?;
+    }
+
+    if args.registry_cache {
+        let (package_infos, interrupted) =
+            registry_cache::measure_registry_cache(&config, args).await?;
+        let baseline = args.compare.as_deref().map(Baseline::load).transpose()?;
+        let target_dir = env::current_dir()?.join("target");
+        let (report, violations) = build_report(
+            package_infos,
+            None,
+            Vec::new(),
+            None,
+            interrupted,
+            baseline,
+            args,
+        )?;
+        return display_report(report, violations, args, &target_dir);
+    }
+
+    let cwd = env::current_dir()?;
+    if let Some(lockfile_path) = &args.lock {
+        let (package_infos, interrupted) =
+            lockfile::measure_lockfile(&config, args, &cwd.join(lockfile_path)).await?;
+        let denied_names = check::denied_crates(args)?;
+        let denied = package_infos
+            .iter()
+            .filter(|info| denied_names.contains(&info.name))
+            .cloned()
+            .collect();
+        let baseline = args.compare.as_deref().map(Baseline::load).transpose()?;
+        let (report, violations) = build_report(
+            package_infos,
+            None,
+            denied,
+            None,
+            interrupted,
+            baseline,
+            args,
+        )?;
+        return display_report(report, violations, args, &cwd.join("target"));
+    }
 
     // Locate the Cargo.toml
-    let manifest_path = find_root_manifest_for_wd(&env::current_dir()?)?;
+    let manifest_paths = match args.manifest_path.as_slice() {
+        [] => vec![find_root_manifest_for_wd(&cwd).map_err(|_| {
+            anyhow!(
+                "No Cargo.toml found in the current directory or any parent. \
+                 Run this inside a Rust project, or pass --manifest-path."
+            )
+        })?],
+        paths => paths
+            .iter()
+            .map(|path| manifest_in(&cwd.join(path)))
+            .collect::<Result<_>>()?,
+    };
+
+    if let Some(output_dir) = &args.output_dir {
+        return batch::analyze_all(&config, args, &manifest_paths, &cwd.join(output_dir), &cwd)
+            .await;
+    }
+    let [manifest_path] = manifest_paths.as_slice() else {
+        bail!("--manifest-path was given several times, that needs --output-dir");
+    };
 
     // Create a workspace from the Cargo.toml
-    let workspace = Workspace::new(&manifest_path, &config)?;
+    let workspace = Workspace::new(manifest_path, &config)?;
 
-    // Calculate and display the total size of each dependency
-    calculate_and_display_depsize(&workspace).await?;
+    if let Some(crate_name) = &args.explain {
+        return explain::explain(&workspace, args, crate_name).await;
+    }
 
-    Ok(())
-}
+    if args.tree {
+        return tree::print_tree(&workspace, args).await;
+    }
 
-/// Formats a size value in bytes as a human-readable string with units of KB, MB, or GB.
-///
-/// # Arguments
-///
-/// * `size` - The size value in bytes to format.
-///
-/// # Returns
-///
-/// Returns a `String` containing the formatted size value with units and byte count.
-///
-/// # Example
-///
-/// ```
-/// assert_eq!(format_size(1024), "1.00KB (1024 bytes)");
-/// assert_eq!(format_size(1048576), "1.00MB (1048576 bytes)");
-/// assert_eq!(format_size(1073741824), "1.00GB (1073741824 bytes)");
-/// assert_eq!(format_size(100), "100 bytes");
-/// ```
-fn format_size(size: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
-
-    if size >= GB {
-        format!("{:.2}GB ({:?} bytes)", size as f64 / GB as f64, size)
-    } else if size >= MB {
-        format!("{:.2}MB ({:?} bytes)", size as f64 / MB as f64, size)
-    } else if size >= KB {
-        format!("{:.2}KB ({:?} bytes)", size as f64 / KB as f64, size)
-    } else {
-        format!("{:?} bytes", size)
+    if args.find_duplicative_content {
+        return overlap::find_duplicative_content(&workspace, args).await;
     }
+
+    // Calculate and display the total size of each dependency
+    calculate_and_display_depsize(&workspace, args).await?;
+
+    Ok(())
 }
 
 /// Asynchronously calculates and displays the size of each dependency package
@@ -77,6 +178,7 @@ fn format_size(size: u64) -> String {
 /// # Arguments
 ///
 /// * `workspace` - A reference to the `Workspace` object representing the current Rust workspace.
+/// * `args` - The parsed command-line arguments.
 ///
 /// # Returns
 ///
@@ -92,151 +194,787 @@ fn format_size(size: u64) -> String {
 /// ```
 /// use cargo::core::Workspace;
 ///
-/// async fn example(workspace: &Workspace<'_>) {
-///     if let Err(e) = calculate_and_display_depsize(workspace).await {
+/// async fn example(workspace: &Workspace<'_>, args: &DepsizeArgs) {
+///     if let Err(e) = calculate_and_display_depsize(workspace, args).await {
 ///         eprintln!("Error: {}", e);
 ///     }
 /// }
 /// ```
-async fn calculate_and_display_depsize(workspace: &Workspace<'_>) -> Result<()> {
-    // Obtain dependency graph
-    // let requested_targets: Vec<CompileKind> = vec![];
-    let mut target_data = RustcTargetData::new(workspace, &[])?;
-    let cli_features = CliFeatures::new_all(true);
-    //let specs: Vec<cargo::core::PackageIdSpec> = vec![];
-    let has_dev_units = HasDevUnits::Yes;
-    let force_all_targets = ForceAllTargets::Yes;
-
-    let workspace_resolve = cargo::ops::resolve_ws_with_opts(
-        workspace,
-        &mut target_data,
-        &[], // requested_targets
-        &cli_features,
-        &[], // specs
-        has_dev_units,
-        force_all_targets,
-        false,
-    )?;
+async fn calculate_and_display_depsize(
+    workspace: &Workspace<'_>,
+    args: &DepsizeArgs,
+) -> Result<()> {
+    let (report, violations) = analyze(workspace, args).await?;
+    let target_dir = workspace.target_dir().into_path_unlocked();
+    display_report(report, violations, args, &target_dir)
+}
 
-    let packages = workspace_resolve.pkg_set.packages();
-    let mut join_set = JoinSet::new();
-    // let semaphore = Arc::new(Semaphore::new(1));
-
-    // Spawn each calculate_package_size task into the JoinSet
-    for package in packages {
-        // let semaphore_clone = semaphore.clone();
-        // Extract and clone necessary data here
-        let package_id = package.package_id().clone();
-        let package_path = package.root().to_path_buf(); // PathBuf is Send
-
-        join_set.spawn(async move {
-            // let _permit = semaphore_clone
-            //     .acquire()
-            //     .await
-            //     .expect("Failed to acquire semaphore");
-            // Now calculate_package_size takes a PathBuf, which is Send
-            match calculate_package_size(&package_path).await {
-                Ok(size) => Ok((package_id, size)),
-                Err(e) => {
-                    eprintln!("Failed to calculate size for {}: {}", package_id.name(), e);
-                    Err(e)
-                }
-            }
-        });
+/// Measures the dependencies of `workspace` and builds the report, without
+/// printing it. Returns the report and how it fails the configured gates.
+async fn analyze(workspace: &Workspace<'_>, args: &DepsizeArgs) -> Result<(Report, Vec<String>)> {
+    if args.format == Some(OutputFormat::NdjsonStream) && args.sum_by != SumBy::Direct {
+        // Each package's subtree is only known once everything is measured
+        bail!(
+            "--format ndjson-stream prints each package's own size, it can't be combined with --sum-by {}",
+            args.sum_by.label()
+        );
     }
+    let Dependencies {
+        packages: package_infos,
+        denied,
+        unique_bytes,
+        path_dependencies,
+        interrupted,
+    } = measure_dependencies(workspace, args, |entry| {
+        if args.format == Some(OutputFormat::NdjsonStream) {
+            entry.print()?;
+        }
+        Ok(())
+    })
+    .await?;
 
-    // let resolve = workspace_resolve.workspace_resolve;
-    let mut package_sizes = HashMap::<PackageId, u64>::new();
+    let self_package = if args.with_self {
+        let package = workspace
+            .current()
+            .context("--with-self needs a package, but this is a virtual workspace")?;
+        let package_id = package.package_id();
+        let label = format!("{} v{}", package_id.name(), package_id.version());
+        let measured = measure_packages(
+            vec![(package_id, label, package.root().to_path_buf())],
+            WalkOptions::from_args(args),
+        )
+        .await?;
+        measured
+            .sizes
+            .get(&package_id)
+            .map(|size| PackageReport::new(package, size))
+    } else {
+        None
+    };
 
-    // Await all spawned tasks and collect their results
-    while let Some(res) = join_set.join_next().await {
-        let (package_id, size) = res?.expect("Failed to join");
-        package_sizes.insert(package_id, size);
-    }
+    let baseline = match (&args.compare, &args.baseline_git_ref) {
+        (Some(path), _) => Some(Baseline::load(path)?),
+        (None, Some(git_ref)) => {
+            let checkout = git_baseline::checkout(workspace.root_manifest(), git_ref)?;
+            let baseline_workspace = Workspace::new(checkout.manifest_path(), workspace.gctx())
+                .with_context(|| format!("failed to load the workspace at `{}`", git_ref))?;
+            let baseline_dependencies = measure_dependencies(&baseline_workspace, args, |_| Ok(()))
+                .await
+                .with_context(|| format!("failed to measure the dependencies at `{}`", git_ref))?;
+            let total_bytes = listed_total(
+                &baseline_dependencies.packages,
+                baseline_dependencies.unique_bytes,
+            );
+            Some(Baseline::from_packages(
+                &baseline_dependencies.packages,
+                total_bytes,
+            ))
+        }
+        (None, None) => None,
+    };
+
+    let (report, mut violations) = build_report(
+        package_infos,
+        self_package,
+        denied,
+        unique_bytes,
+        interrupted,
+        baseline,
+        args,
+    )?;
+    violations.extend(check::path_dependency_violations(&path_dependencies, args));
+    Ok((report, violations))
+}
+
+/// The measured dependencies of a workspace.
+struct Dependencies {
+    /// One entry per listed dependency.
+    packages: Vec<PackageReport>,
+    /// Every resolved package named by `--deny-crate` or `--deny-file`,
+    /// listed or not.
+    denied: Vec<PackageReport>,
+    /// With `--sum-by unique`, the size of every package the listed ones pull
+    /// in, counting each once.
+    unique_bytes: Option<u64>,
+    /// With `--verify-no-path-deps`, every resolved path package that isn't
+    /// a workspace member.
+    path_dependencies: Vec<PackageReport>,
+    /// Whether the scan was interrupted.
+    interrupted: bool,
+}
+
+/// Resolves the workspace and measures its direct dependencies, passing each
+/// listed one to `on_listed` as soon as it's measured.
+async fn measure_dependencies(
+    workspace: &Workspace<'_>,
+    args: &DepsizeArgs,
+    mut on_listed: impl FnMut(StreamEntry) -> Result<()>,
+) -> Result<Dependencies> {
+    // Obtain dependency graph
+    let workspace_resolve = resolve_dependencies(workspace, args)?;
+    let patched = patched_packages(workspace, &workspace_resolve)?;
+    let members: Vec<PackageId> = workspace
+        .members()
+        .map(|member| member.package_id())
+        .collect();
+    let mut graph = tree::dependency_graph(&workspace_resolve.targeted_resolve, args);
+    let ignored = match &args.ignore_build_deps_of {
+        Some(crate_name) => cut_build_dependencies(
+            &mut graph,
+            &workspace_resolve.targeted_resolve,
+            &members,
+            crate_name,
+        )?,
+        None => HashSet::new(),
+    };
 
     let root_package = workspace.current()?;
-    let root_deps = root_package
-        .dependencies()
-        .iter()
-        .filter(|dep| dep.kind() == DepKind::Normal);
-
-    // Identify the latest versions of each package among root dependencies
-    // Collecting unique names of root dependencies
-    let dep_names: HashSet<String> = root_deps
-        .map(|dep| dep.package_name().to_string())
+    let gated = if args.strict_target {
+        let (_, gated) = resolve::platform_gated(workspace, args)?;
+        gated.into_iter().map(|(_, dep)| dep).collect()
+    } else {
+        HashSet::new()
+    };
+    let dep_kinds = direct_dependencies(root_package, args, &gated);
+
+    // Resolving each root dependency name to its latest version, once per
+    // source it comes from
+    let latest_versions: HashSet<PackageId> = dep_kinds
+        .keys()
+        .flat_map(|name| {
+            resolve::packages_named(&workspace_resolve, root_package.package_id(), name)
+        })
+        .filter(|package_id| !ignored.contains(package_id))
         .collect();
 
-    // Resolving each dependency name to its latest version
-    let latest_versions: HashSet<PackageId> = dep_names
-        .into_iter()
-        .filter_map(|name| {
-            workspace_resolve
-                .pkg_set
-                .packages()
-                .filter(|pkg| pkg.name() == name.as_str())
-                .max_by_key(|pkg| pkg.version())
-                .map(|pkg| pkg.package_id().clone())
+    // With --allowlist: every resolved package that isn't approved
+    let mut listed: HashSet<PackageId> = if let Some(path) = &args.allowlist {
+        let allowlist = check::Allowlist::load(path)?;
+        let members: HashSet<PackageId> = workspace
+            .members()
+            .map(|member| member.package_id())
+            .collect();
+        workspace_resolve
+            .pkg_set
+            .package_ids()
+            .filter(|package_id| !members.contains(package_id))
+            .filter(|package_id| {
+                !allowlist.allows(
+                    package_id.name().as_str(),
+                    &package_id.version().to_string(),
+                )
+            })
+            .filter(|package_id| !ignored.contains(package_id))
+            .collect()
+    } else if args.only_duplicates {
+        // Every version of each name resolved more than once
+        let mut versions: HashMap<&str, Vec<PackageId>> = HashMap::new();
+        for package_id in workspace_resolve.pkg_set.package_ids() {
+            versions
+                .entry(package_id.name().as_str())
+                .or_default()
+                .push(package_id);
+        }
+        versions
+            .into_values()
+            .filter(|package_ids| package_ids.len() > 1)
+            .flatten()
+            .filter(|package_id| !ignored.contains(package_id))
+            .collect()
+    } else {
+        latest_versions
+    };
+
+    // Only the listed packages are streamed, so they add up to the final total
+    if let Some(package_type) = args.package_type {
+        let mut of_type = HashSet::new();
+        for package_id in listed {
+            if package_type.matches(workspace_resolve.pkg_set.get_one(package_id)?) {
+                of_type.insert(package_id);
+            }
+        }
+        listed = of_type;
+    }
+
+    let packages = workspace_resolve
+        .pkg_set
+        .packages()
+        .map(|package| {
+            let package_id = package.package_id();
+            let label = format!("{} v{}", package_id.name(), package_id.version());
+            (package_id, label, package.root().to_path_buf())
         })
         .collect();
+    let Measured {
+        sizes: package_sizes,
+        interrupted,
+    } = size_index::measure_packages_indexed(
+        workspace.gctx(),
+        args,
+        packages,
+        |package_id, size| {
+            if listed.contains(&package_id) {
+                let package = workspace_resolve.pkg_set.get_one(package_id)?;
+                on_listed(StreamEntry::new(package, size, args))?;
+            }
+            Ok(())
+        },
+    )
+    .await?;
+
+    if args.verify_checksums {
+        let checksums = workspace_resolve.targeted_resolve.checksums();
+        let expected = workspace_resolve
+            .pkg_set
+            .packages()
+            .filter_map(|package| {
+                let package_id = package.package_id();
+                let checksum = checksums.get(&package_id)?.clone()?;
+                Some(checksum::Expected {
+                    label: format!("{} v{}", package_id.name(), package_id.version()),
+                    root: package.root().to_path_buf(),
+                    checksum,
+                })
+            })
+            .collect();
+        checksum::verify_checksums(expected).await?;
+    }
+
+    let yanked = yanked::yanked_packages(
+        workspace.gctx(),
+        &workspace_resolve.pkg_set,
+        workspace_resolve.pkg_set.package_ids(),
+    )?;
+
+    // A member and a package from elsewhere going by the same name are
+    // easily mistaken for each other, list them with their source
+    let mut shared_names = HashSet::new();
+    for (member, other) in resolve::member_name_collisions(workspace, &workspace_resolve) {
+        eprintln!(
+            "warning: workspace member {} v{} shares its name with {} v{} from {}, \
+             they are listed separately by source",
+            member.name(),
+            member.version(),
+            other.name(),
+            other.version(),
+            other.source_id()
+        );
+        shared_names.insert(member.name());
+    }
+
+    let artifact_sizes = args.with_artifacts.then(|| {
+        let roots = artifacts::PackageRoots::new(workspace_resolve.pkg_set.packages());
+        let target_dir = workspace.target_dir().into_path_unlocked();
+        artifacts::artifact_sizes(&target_dir, &roots)
+    });
+
+    let compile_times = args
+        .compile_time
+        .then(|| compile_time::compile_times(workspace, args))
+        .transpose()?;
+
+    // With --sum-by transitive or unique: what each listed package pulls in
+    let reachable: HashMap<PackageId, HashSet<PackageId>> = match args.sum_by {
+        SumBy::Direct => HashMap::new(),
+        SumBy::Transitive | SumBy::Unique => listed
+            .iter()
+            .map(|&package_id| (package_id, tree::reachable(&graph, package_id)))
+            .collect(),
+    };
+    let sum_sizes = |package_ids: &HashSet<PackageId>| -> PackageSize {
+        let mut sum = PackageSize::default();
+        for size in package_ids.iter().filter_map(|id| package_sizes.get(id)) {
+            saturating::add(&mut sum.bytes, size.bytes);
+            saturating::add(&mut sum.files, size.files);
+        }
+        sum
+    };
+    let unique_bytes = (args.sum_by == SumBy::Unique).then(|| {
+        let every: HashSet<PackageId> = reachable.values().flatten().copied().collect();
+        sum_sizes(&every).bytes
+    });
 
-    let mut sum: u64 = 0;
     let mut package_infos = Vec::new();
 
-    // Loop over the latest_versions HashSet
-    for package_id in latest_versions.iter() {
+    // Loop over the listed packages
+    for package_id in listed.iter() {
         // Check if the package_id is in the package_sizes HashMap
-        if let Some(&size) = package_sizes.get(package_id) {
-            // Get the package from the package set to print its name and version
-            if let Ok(package) = workspace_resolve.pkg_set.get_one(*package_id) {
-                let name_ver = format!("{} (v{})", package.name(), package.version());
-                package_infos.push((name_ver, size));
-                sum += size;
+        if let Some(size) = package_sizes.get(package_id) {
+            let package = workspace_resolve.pkg_set.get_one(*package_id)?;
+            if size.depth_capped {
+                eprintln!(
+                    "warning: {} v{} has directories nested deeper than --max-depth-files, \
+                     its size is a lower bound",
+                    package_id.name(),
+                    package_id.version()
+                );
+            }
+            if size.special_files > 0 {
+                eprintln!(
+                    "warning: {} v{} contains {} special files (FIFOs, sockets or devices), \
+                     they were not counted",
+                    package_id.name(),
+                    package_id.version(),
+                    size.special_files
+                );
+            }
+            let mut info = PackageReport::new(package, size);
+            if args.estimate_install_time {
+                info.estimate_install_time(args.bandwidth);
+            }
+            if let Some(reachable) = reachable.get(package_id) {
+                let subtree = sum_sizes(reachable);
+                info.bytes = subtree.bytes;
+                info.files = subtree.files;
+            }
+            info.show_source = shared_names.contains(&package_id.name());
+            info.patched = patched.contains(package_id);
+            info.yanked = yanked.contains(package_id);
+            if args.with_urls {
+                info.url = Some(report::source_url(*package_id));
+            }
+            if args.show_path {
+                info.path = Some(package.root().to_path_buf());
+            }
+            if args.breakdown_dirs {
+                info.dirs = Some(size.dirs);
+            }
+            if args.records_files() {
+                info.record_file_sizes();
+            }
+            if let Some(group_by) = args.group_by {
+                info.groups = group::group_keys(package, group_by, args.author_attribution);
             }
+            if args.metadata_overhead {
+                info.metadata_bytes = Some(size.metadata_bytes);
+            }
+            if args.disk_impact {
+                info.add_archive_size();
+            }
+            if args.dedupe_kinds {
+                info.kinds = dep_kinds
+                    .get(package_id.name().as_str())
+                    .map(|kinds| kinds.iter().copied().map(dep_kind_label).collect());
+            }
+            if let Some(artifact_sizes) = &artifact_sizes {
+                let artifact_bytes = artifact_sizes.get(package_id).copied().unwrap_or(0);
+                info.artifact_bytes = Some(artifact_bytes);
+                if args.as_percentage_of_binary && artifact_bytes > 0 {
+                    info.source_percent_of_artifacts =
+                        Some(info.bytes as f64 / artifact_bytes as f64 * 100.0);
+                }
+            }
+            if let Some(compile_times) = &compile_times {
+                let key = (
+                    package_id.name().to_string(),
+                    package_id.version().to_string(),
+                );
+                info.compile_seconds = Some(compile_times.get(&key).copied().unwrap_or(0.0));
+            }
+            package_infos.push(info);
         }
     }
 
-    // Sort the vector by size (second element of the tuple)
-    package_infos.sort_by_key(|k| k.1);
+    let denied_names = check::denied_crates(args)?;
+    let mut denied: Vec<PackageReport> = workspace_resolve
+        .pkg_set
+        .packages()
+        .filter(|package| denied_names.contains(package.name().as_str()))
+        .filter_map(|package| {
+            let size = package_sizes.get(&package.package_id())?;
+            Some(PackageReport::new(package, size))
+        })
+        .collect();
+    denied.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+    let path_dependencies = if args.verify_no_path_deps {
+        let members: HashSet<PackageId> = workspace
+            .members()
+            .map(|member| member.package_id())
+            .collect();
+        workspace_resolve
+            .pkg_set
+            .packages()
+            .filter(|package| package.package_id().source_id().is_path())
+            .filter(|package| !members.contains(&package.package_id()))
+            .filter_map(|package| {
+                let size = package_sizes.get(&package.package_id())?;
+                Some(PackageReport::new(package, size))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(Dependencies {
+        packages: package_infos,
+        denied,
+        unique_bytes,
+        path_dependencies,
+        interrupted,
+    })
+}
 
-    // Now iterate over the sorted vector (asc order)
-    for (name_ver, size) in package_infos {
-        println!("{: <25} : {}", name_ver, format_size(size));
+/// The manifest `--manifest-path` refers to, which may also be given as the
+/// directory containing it.
+fn manifest_in(path: &Path) -> Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
     }
+    let manifest = path.join("Cargo.toml");
+    if !manifest.is_file() {
+        bail!("--manifest-path: no Cargo.toml in {}", path.display());
+    }
+    Ok(manifest)
+}
 
-    println!("> Total size: {}", format_size(sum));
+/// Whether `path` may be written: it doesn't exist yet, `--assume-yes` was
+/// given, we're not in a terminal to ask, or the user said yes.
+fn confirm_overwrite(path: &Path, args: &DepsizeArgs) -> Result<bool> {
+    if !path.exists() || args.assume_yes || !std::io::stdin().is_terminal() {
+        return Ok(true);
+    }
+    eprint!("{} already exists, overwrite it? [y/N] ", path.display());
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let confirmed = matches!(answer.trim(), "y" | "Y" | "yes");
+    if !confirmed {
+        eprintln!("note: left {} unchanged", path.display());
+    }
+    Ok(confirmed)
+}
 
-    Ok(())
+/// How `--dedupe-kinds` names a dependency kind.
+fn dep_kind_label(kind: DepKind) -> &'static str {
+    match kind {
+        DepKind::Normal => "normal",
+        DepKind::Development => "dev",
+        DepKind::Build => "build",
+    }
 }
 
-async fn calculate_package_size(package_path: &std::path::Path) -> Result<u64> {
-    // let package_path = package.root();
-    let walker = ignore::WalkBuilder::new(package_path).build();
-    let mut total_size = 0;
-
-    for entry in walker {
-        match entry {
-            Ok(entry) => {
-                if entry.file_type().unwrap().is_file() {
-                    let metadata = fs::metadata(entry.path()).await?;
-                    total_size += metadata.len();
-                }
+/// The total of the listed packages: `unique_bytes` with `--sum-by unique`,
+/// otherwise their sizes with packages sharing a directory counted once.
+fn listed_total(package_infos: &[PackageReport], unique_bytes: Option<u64>) -> u64 {
+    // Packages sharing a directory are listed separately but take up its
+    // space once
+    let mut counted_roots = HashSet::new();
+    unique_bytes.unwrap_or_else(|| {
+        saturating::sum(
+            package_infos
+                .iter()
+                .filter(|info| info.root.as_os_str().is_empty() || counted_roots.insert(&info.root))
+                .map(|info| info.bytes),
+        )
+    })
+}
+
+/// Applies the listing options to the measured packages and compares them
+/// with `baseline`, if any.
+///
+/// `self_package` is the project's own package for `--with-self`, `denied`
+/// the resolved packages on the denylist and `unique_bytes` the total with
+/// `--sum-by unique`. Returns the report along with every
+/// way it fails the configured gates.
+fn build_report(
+    mut package_infos: Vec<PackageReport>,
+    self_package: Option<PackageReport>,
+    denied: Vec<PackageReport>,
+    unique_bytes: Option<u64>,
+    interrupted: bool,
+    baseline: Option<Baseline>,
+    args: &DepsizeArgs,
+) -> Result<(Report, Vec<String>)> {
+    let package_count = package_infos.len();
+    let mut violations = check::package_violations(&package_infos, args);
+    if args.strip_prerelease {
+        for info in &mut package_infos {
+            info.strip_prerelease = true;
+        }
+    }
+    let sum = listed_total(&package_infos, unique_bytes);
+    let install_seconds = args.estimate_install_time.then(|| {
+        package_infos
+            .iter()
+            .filter_map(|info| info.install_seconds)
+            .sum()
+    });
+    let mut estimated_roots = HashSet::new();
+    let estimate = args.sample_rate.map(|sample_rate| {
+        Estimate::new(
+            sample_rate,
+            package_infos
+                .iter()
+                .filter(|info| {
+                    info.root.as_os_str().is_empty() || estimated_roots.insert(&info.root)
+                })
+                .map(|info| info.estimate_variance),
+        )
+    });
+    let deps_over = args.summary_only_deps_over.map(|threshold_bytes| DepsOver {
+        threshold_bytes,
+        count: package_infos
+            .iter()
+            .filter(|info| info.bytes > threshold_bytes)
+            .count(),
+    });
+
+    let percentiles = args
+        .percentiles
+        .then(|| Percentiles::new(package_infos.iter().map(|info| info.bytes).collect()));
+
+    let metadata_overhead = args.metadata_overhead.then(|| {
+        let mut overhead = GroupTotal::default();
+        for info in &package_infos {
+            overhead.add(info.metadata_bytes.unwrap_or(0));
+        }
+        overhead
+    });
+
+    // Against every measured package, the folding and hiding below only
+    // change what's listed
+    let comparison = baseline
+        .as_ref()
+        .map(|baseline| Comparison::new(baseline, &package_infos, sum));
+    let file_diff = match (&baseline, &args.diff_files) {
+        (Some(baseline), Some(crate_name)) => {
+            Some(FileDiff::new(baseline, &package_infos, crate_name)?)
+        }
+        _ => None,
+    };
+
+    // Every measured package, so the buckets and groups add up to the total
+    let histogram = args.histogram.then(|| Report::histogram(&package_infos));
+    let grouping = args
+        .group_by
+        .map(|group_by| group::group_packages(&package_infos, group_by));
+
+    // Fold proc-macro crates into a single summary row
+    let proc_macros = args.aggregate_proc_macros.then(|| {
+        let mut proc_macros = GroupTotal::default();
+        package_infos.retain(|info| {
+            if info.proc_macro {
+                proc_macros.add(info.bytes);
+            }
+            !info.proc_macro
+        });
+        proc_macros
+    });
+
+    // Fold packages under --collapse-under into a single row
+    let collapsed = args.collapse_under.map(|min_bytes| {
+        let mut collapsed = GroupTotal::default();
+        package_infos.retain(|info| {
+            if info.bytes < min_bytes {
+                collapsed.add(info.bytes);
             }
-            Err(err) => eprintln!("Error: {}", err),
+            info.bytes >= min_bytes
+        });
+        collapsed
+    });
+
+    if let Some(min_pct) = args.min_contribution {
+        if sum > 0 {
+            package_infos.retain(|info| info.bytes as f64 / sum as f64 * 100.0 >= min_pct);
         }
     }
 
-    Ok(total_size)
+    report::sort_packages(
+        &mut package_infos,
+        args.sort,
+        args.sort_direction
+            .unwrap_or_else(|| args.sort.default_direction()),
+        args.sort_secondary,
+    );
+
+    let mut report = Report {
+        packages: package_infos,
+        proc_macros,
+        collapsed,
+        self_package,
+        denied,
+        package_count,
+        total_bytes: sum,
+        deps_over,
+        percentiles,
+        metadata_overhead,
+        install_seconds,
+        estimate,
+        interrupted,
+        sum_by: args.sum_by.label(),
+        histogram,
+        grouping,
+        comparison,
+        file_diff,
+        ..Default::default()
+    };
+
+    if args.only_duplicates {
+        report.duplicates = Some(group::group_by_name(&report.packages));
+    }
+    if let Some(limit) = args.top_files {
+        report.top_files = Some(report.top_files(limit, args.absolute_paths));
+    }
+
+    violations.extend(check::report_violations(&report, args));
+    Ok((report, violations))
+}
+
+/// Prints the report in the requested format and enforces the configured
+/// gates; `target_dir` is where `--summary-json` writes by default.
+fn display_report(
+    report: Report,
+    violations: Vec<String>,
+    args: &DepsizeArgs,
+    target_dir: &Path,
+) -> Result<()> {
+    if args.check {
+        if !check::has_gates(args) {
+            eprintln!(
+                "warning: --check has nothing to check, configure a gate such as \
+                 --max-total-size, --max-package-size, --deny-crate or --sum-threshold-percent"
+            );
+        }
+        check::print_verdict(&report, &violations, args);
+    } else if args.format == Some(OutputFormat::NdjsonStream) {
+        // The packages were already printed as they were measured
+        outln!(
+            "{}",
+            serde_json::json!({
+                "total_bytes": report.total_bytes,
+                "package_count": report.package_count,
+            })
+        );
+    } else if args.total_bytes {
+        outln!("{}", report.total_bytes);
+    } else if args.badge {
+        outln!(
+            "{}",
+            serde_json::to_string(&Badge::new(report.total_bytes, args))?
+        );
+    } else if let Some(template) = &args.output_template_file {
+        out!("{}", template.render(&report, args.size_format())?);
+    } else if args.json {
+        report.print_json(args.pretty)?;
+    } else if args.toml {
+        report.print_toml()?;
+    } else {
+        report.print_human(args);
+    }
+
+    if args.summary_json.is_some() && report.interrupted {
+        // A later run would read the partial totals as complete ones
+        eprintln!("note: the scan was interrupted, no summary was written");
+    } else if let Some(path) = &args.summary_json {
+        // The default location is regenerated on every run, only a path the
+        // user chose may hold something worth keeping
+        let path = match path {
+            Some(path) if !confirm_overwrite(path, args)? => None,
+            Some(path) => Some(path.clone()),
+            None => Some(target_dir.join("depsize-summary.json")),
+        };
+        if let Some(path) = path {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let summary = serde_json::to_string_pretty(&report.summary())?;
+            std::fs::write(&path, summary + "\n")
+                .with_context(|| format!("failed to write summary to {}", path.display()))?;
+        }
+    }
+
+    if report.interrupted {
+        // Conventional exit status for SIGINT; the gates below would only be
+        // judging a partial total
+        exit(130);
+    }
+
+    if args.check && !violations.is_empty() {
+        // The verdict above already lists the violations
+        exit(1);
+    }
+    if !violations.is_empty() {
+        bail!("{}", violations.join("\n"));
+    }
+
+    if let Some(path) = &args.log_history {
+        let path = match path {
+            Some(path) => path.clone(),
+            None => PathBuf::from("depsize-history.csv"),
+        };
+        history::append(&path, &report, &env::current_dir()?)?;
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cargo::core::Shell;
+    use clap::Parser;
+    use cli::CargoCli;
+
+    #[tokio::test]
+    async fn test_streamed_packages_add_up_to_the_total() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        let manifest = |name: &str, dependencies: &str| {
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+                 [dependencies]\n{}",
+                name, dependencies
+            )
+        };
+        write(
+            "app/Cargo.toml",
+            &manifest("app", "a = { path = \"../a\" }\nb = { path = \"../b\" }\n"),
+        );
+        write("b/Cargo.toml", &manifest("b", "c = { path = \"../c\" }\n"));
+        for name in ["a", "c"] {
+            write(&format!("{}/Cargo.toml", name), &manifest(name, ""));
+        }
+        for (name, bytes) in [("app", 1000), ("a", 10), ("b", 20), ("c", 500)] {
+            write(&format!("{}/src/lib.rs", name), &"x".repeat(bytes));
+        }
+
+        let gctx = GlobalContext::new(
+            Shell::new(),
+            dir.path().to_path_buf(),
+            dir.path().join("cargo-home"),
+        );
+        let workspace = Workspace::new(&dir.path().join("app/Cargo.toml"), &gctx).unwrap();
+        let CargoCli::Depsize(args) =
+            CargoCli::parse_from(["cargo", "depsize", "--offline", "--format", "ndjson-stream"]);
+        let mut streamed = Vec::new();
+        let dependencies = measure_dependencies(&workspace, &args, |entry| {
+            streamed.push(entry);
+            Ok(())
+        })
+        .await
+        .unwrap();
+        let (report, _) = build_report(
+            dependencies.packages,
+            None,
+            dependencies.denied,
+            dependencies.unique_bytes,
+            dependencies.interrupted,
+            None,
+            &args,
+        )
+        .unwrap();
 
-    #[test]
-    fn test_format_size() {
-        assert_eq!(format_size(1024), "1.00KB (1024 bytes)");
-        assert_eq!(format_size(1048576), "1.00MB (1048576 bytes)");
-        assert_eq!(format_size(1073741824), "1.00GB (1073741824 bytes)");
-        assert_eq!(format_size(100), "100 bytes");
+        let mut names: Vec<&str> = streamed.iter().map(|entry| entry.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(
+            streamed.iter().map(|entry| entry.bytes).sum::<u64>(),
+            report.total_bytes
+        );
     }
 }
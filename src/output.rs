@@ -0,0 +1,58 @@
+//! The way everything meant for stdout gets there. With `--clipboard` it's
+//! also kept, to be copied once the command is done.
+
+use std::fmt::{self, Write};
+use std::sync::Mutex;
+
+/// Everything printed since [`capture`] was called, if it was.
+static CAPTURED: Mutex<Option<String>> = Mutex::new(None);
+
+/// `print!`, kept for `--clipboard`.
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::print(format_args!($($arg)*))
+    };
+}
+
+/// `println!`, kept for `--clipboard`.
+macro_rules! outln {
+    () => {
+        $crate::output::print(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+pub fn print(args: fmt::Arguments) {
+    if let Some(captured) = CAPTURED.lock().unwrap().as_mut() {
+        let _ = captured.write_fmt(args);
+    }
+    print!("{}", args);
+}
+
+/// Starts keeping everything printed.
+pub fn capture() {
+    *CAPTURED.lock().unwrap() = Some(String::new());
+}
+
+/// Everything printed since [`capture`], which stops keeping it.
+pub fn take_captured() -> Option<String> {
+    CAPTURED.lock().unwrap().take()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_keeps_what_is_printed() {
+        out!("not kept\n");
+        capture();
+        out!("{} : ", "serde");
+        outln!("{}", 10);
+        outln!();
+        assert_eq!(take_captured().as_deref(), Some("serde : 10\n\n"));
+        assert_eq!(take_captured(), None);
+    }
+}
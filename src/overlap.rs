@@ -0,0 +1,162 @@
+use crate::cli::DepsizeArgs;
+use crate::report::format_size;
+use crate::resolve::resolve_dependencies;
+use crate::saturating;
+use crate::walk::{package_walker, WalkOptions};
+use anyhow::{Context, Result};
+use cargo::core::{PackageId, Workspace};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use tokio::task::JoinSet;
+
+/// Pairs sharing less than this share of the smaller package are not reported.
+const MIN_OVERLAP_PERCENT: f64 = 50.0;
+
+/// License texts are identical across thousands of unrelated crates.
+const LICENSE_PREFIXES: [&str; 4] = ["LICENSE", "LICENCE", "COPYING", "NOTICE"];
+
+/// Two packages and the bytes of file content they have in common.
+struct Overlap {
+    a: PackageId,
+    b: PackageId,
+    shared_bytes: u64,
+    /// `shared_bytes` as a share of the smaller package.
+    percent: f64,
+}
+
+/// Hashes the contents of every file of every resolved package and prints
+/// the pairs of packages whose content mostly overlaps, largest first.
+pub async fn find_duplicative_content(workspace: &Workspace<'_>, args: &DepsizeArgs) -> Result<()> {
+    let workspace_resolve = resolve_dependencies(workspace, args)?;
+    let options = WalkOptions::from_args(args);
+
+    let mut join_set = JoinSet::new();
+    let mut roots = HashMap::new();
+    for package in workspace_resolve.pkg_set.packages() {
+        let package_id = package.package_id();
+        let root = package.root().to_path_buf();
+        roots.insert(package_id, root.clone());
+        join_set.spawn_blocking(move || {
+            hash_files(&root, options)
+                .map(|files| (package_id, files))
+                .with_context(|| {
+                    format!(
+                        "failed to hash the files of {} v{}",
+                        package_id.name(),
+                        package_id.version()
+                    )
+                })
+        });
+    }
+
+    let mut packages = HashMap::new();
+    while let Some(res) = join_set.join_next().await {
+        let (package_id, files) = res??;
+        packages.insert(package_id, files);
+    }
+
+    // A package nested in another's directory, like a path dependency inside
+    // the workspace, is part of its content rather than a copy of it
+    let overlaps: Vec<Overlap> = overlaps(&packages)
+        .into_iter()
+        .filter(|overlap| {
+            let (a, b) = (&roots[&overlap.a], &roots[&overlap.b]);
+            !a.starts_with(b) && !b.starts_with(a)
+        })
+        .collect();
+    if overlaps.is_empty() {
+        outln!("No packages with mostly overlapping content found.");
+        return Ok(());
+    }
+    for overlap in overlaps {
+        outln!(
+            "{} v{} and {} v{} share {} ({:.0}% of the smaller)",
+            overlap.a.name(),
+            overlap.a.version(),
+            overlap.b.name(),
+            overlap.b.version(),
+            format_size(overlap.shared_bytes, args.size_format()),
+            overlap.percent
+        );
+    }
+
+    Ok(())
+}
+
+/// Maps the content hash of every file in a package, license texts aside,
+/// to its size.
+fn hash_files(package_path: &Path, options: WalkOptions) -> Result<HashMap<u64, u64>> {
+    let mut files = HashMap::new();
+    for entry in package_walker(package_path, options).build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                options.on_error.handle(err.into())?;
+                continue;
+            }
+        };
+        let is_file = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file());
+        let file_name = entry.file_name().to_string_lossy();
+        if !is_file
+            || LICENSE_PREFIXES
+                .iter()
+                .any(|prefix| file_name.starts_with(prefix))
+        {
+            continue;
+        }
+
+        match std::fs::read(entry.path())
+            .with_context(|| format!("failed to read {}", entry.path().display()))
+        {
+            Ok(contents) => {
+                let mut hasher = DefaultHasher::new();
+                contents.hash(&mut hasher);
+                files.insert(hasher.finish(), contents.len() as u64);
+            }
+            Err(err) => options.on_error.handle(err)?,
+        }
+    }
+    Ok(files)
+}
+
+/// Every pair of packages sharing at least [`MIN_OVERLAP_PERCENT`] of the
+/// smaller one's content, by most shared bytes.
+fn overlaps(packages: &HashMap<PackageId, HashMap<u64, u64>>) -> Vec<Overlap> {
+    let mut owners: HashMap<u64, Vec<PackageId>> = HashMap::new();
+    for (package_id, files) in packages {
+        for hash in files.keys() {
+            owners.entry(*hash).or_default().push(*package_id);
+        }
+    }
+
+    let mut shared: BTreeMap<(PackageId, PackageId), u64> = BTreeMap::new();
+    for (hash, mut owners) in owners {
+        owners.sort();
+        for (i, a) in owners.iter().enumerate() {
+            for b in &owners[i + 1..] {
+                saturating::add(shared.entry((*a, *b)).or_default(), packages[a][&hash]);
+            }
+        }
+    }
+
+    let total = |package_id: &PackageId| saturating::sum(packages[package_id].values().copied());
+    let mut overlaps: Vec<Overlap> = shared
+        .into_iter()
+        .filter_map(|((a, b), shared_bytes)| {
+            let smaller = total(&a).min(total(&b));
+            let percent = shared_bytes as f64 / smaller as f64 * 100.0;
+            (percent >= MIN_OVERLAP_PERCENT).then_some(Overlap {
+                a,
+                b,
+                shared_bytes,
+                percent,
+            })
+        })
+        .collect();
+    overlaps.sort_by_key(|overlap| std::cmp::Reverse(overlap.shared_bytes));
+    overlaps
+}
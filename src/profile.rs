@@ -0,0 +1,226 @@
+use crate::cli::{CargoCli, ConfigProfile, DepsizeArgs};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command, CommandFactory, FromArgMatches, ValueEnum};
+use std::ffi::OsString;
+
+/// One flag a [`ConfigProfile`] adds to the command line.
+struct Preset {
+    /// The argument it sets.
+    id: &'static str,
+    flags: &'static [&'static str],
+    /// Only added when one of these is given, for flags that need them.
+    needs_one_of: &'static [&'static str],
+}
+
+const fn preset(id: &'static str, flags: &'static [&'static str]) -> Preset {
+    Preset {
+        id,
+        flags,
+        needs_one_of: &[],
+    }
+}
+
+const AUDIT: &[Preset] = &[
+    preset("only_duplicates", &["--only-duplicates"]),
+    preset("group_by", &["--group-by", "license"]),
+    preset("columns", &["--columns", "name,version,size,source"]),
+];
+
+const CI: &[Preset] = &[
+    preset("json", &["--json"]),
+    preset("no_progress", &["--no-progress"]),
+    preset("color", &["--color", "never"]),
+    preset("deny_yanked", &["--deny-yanked"]),
+    Preset {
+        id: "sum_threshold_percent",
+        flags: &["--sum-threshold-percent", "5"],
+        needs_one_of: &["compare", "baseline_git_ref"],
+    },
+];
+
+const DISK: &[Preset] = &[
+    preset("disk_impact", &["--disk-impact"]),
+    preset("registry_cache", &["--registry-cache"]),
+];
+
+const MINIMAL: &[Preset] = &[
+    preset("total_bytes", &["--total-bytes"]),
+    preset("no_progress", &["--no-progress"]),
+];
+
+impl ConfigProfile {
+    fn presets(self) -> &'static [Preset] {
+        match self {
+            ConfigProfile::Audit => AUDIT,
+            ConfigProfile::Ci => CI,
+            ConfigProfile::Disk => DISK,
+            ConfigProfile::Minimal => MINIMAL,
+        }
+    }
+}
+
+/// Parses the command line, expanding `--config-profile`.
+///
+/// The preset's flags are appended to the given ones and the whole line is
+/// parsed again, so clap checks them like typed ones. A preset flag is left
+/// out when the command line or its environment variable already sets the
+/// same argument, or when adding it makes the line conflict.
+pub fn parse_args() -> DepsizeArgs {
+    try_parse_args_from(std::env::args_os().collect()).unwrap_or_else(|err| err.exit())
+}
+
+fn try_parse_args_from(argv: Vec<OsString>) -> Result<DepsizeArgs, clap::Error> {
+    let mut command = CargoCli::command();
+    command.build();
+    let matches = command.clone().try_get_matches_from(&argv)?;
+    let CargoCli::Depsize(args) = CargoCli::from_arg_matches(&matches)?;
+    let Some(profile) = args.config_profile else {
+        return Ok(args);
+    };
+
+    let (_, depsize) = matches.subcommand().expect("depsize is required");
+    let expanded = expand(
+        &command,
+        argv.clone(),
+        &given_ids(depsize),
+        profile.presets(),
+    )
+    .map_err(|err| {
+        let name = profile.to_possible_value().expect("no profile is skipped");
+        profile_error(&mut command, name.get_name(), &err)
+    })?;
+
+    let matches = command.try_get_matches_from(&expanded)?;
+    let CargoCli::Depsize(args) = CargoCli::from_arg_matches(&matches)?;
+    Ok(args)
+}
+
+/// Appends each of `presets` to `argv` that isn't `given` and whose needs are
+/// met, checking the line with every flag added against all of clap's rules.
+/// Flags that would conflict are dropped, any other failure is returned.
+fn expand(
+    command: &Command,
+    mut argv: Vec<OsString>,
+    given: &[&str],
+    presets: &[Preset],
+) -> Result<Vec<OsString>, (&'static [&'static str], clap::Error)> {
+    for preset in presets {
+        let needs_met = preset.needs_one_of.is_empty()
+            || preset.needs_one_of.iter().any(|id| given.contains(id));
+        if !needs_met || given.contains(&preset.id) {
+            continue;
+        }
+        let mut candidate = argv.clone();
+        candidate.extend(preset.flags.iter().map(OsString::from));
+        match command.clone().try_get_matches_from(&candidate) {
+            Ok(_) => argv = candidate,
+            Err(err) if err.kind() == ErrorKind::ArgumentConflict => {}
+            Err(err) => return Err((preset.flags, err)),
+        }
+    }
+    Ok(argv)
+}
+
+/// Blames the failure of a preset's `flags` on the profile `name`.
+fn profile_error(
+    command: &mut Command,
+    name: &str,
+    (flags, err): &(&[&str], clap::Error),
+) -> clap::Error {
+    let rendered = err.render().to_string();
+    let reason = rendered
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches("error: ");
+    command.error(
+        err.kind(),
+        format!(
+            "`--config-profile {}` adds `{}`, but {}",
+            name,
+            flags.join(" "),
+            reason
+        ),
+    )
+}
+
+/// The arguments set on the command line or through their environment
+/// variable, rather than by default.
+fn given_ids(matches: &ArgMatches) -> Vec<&str> {
+    matches
+        .ids()
+        .map(|id| id.as_str())
+        .filter(|id| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<OsString> {
+        ["cargo-depsize", "depsize"]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect()
+    }
+
+    fn parse(args: &[&str]) -> DepsizeArgs {
+        try_parse_args_from(argv(args)).unwrap()
+    }
+
+    #[test]
+    fn test_config_profile_yields_to_given_flags() {
+        let args = parse(&["--config-profile", "ci"]);
+        assert!(args.json && args.no_progress && args.deny_yanked);
+        assert_eq!(args.sum_threshold_percent, None);
+
+        let args = parse(&["--config-profile", "ci", "--toml", "--compare", "old.json"]);
+        assert!(args.toml && !args.json);
+        assert_eq!(args.sum_threshold_percent, Some(5.0));
+
+        let args = parse(&["--config-profile", "audit"]);
+        assert!(args.only_duplicates && args.group_by.is_some());
+        let args = parse(&["--config-profile", "audit", "--histogram"]);
+        assert!(!args.only_duplicates && args.group_by.is_some());
+
+        let args = parse(&["--config-profile", "disk"]);
+        assert!(args.disk_impact && args.registry_cache);
+        let args = parse(&["--config-profile", "disk", "--lock", "Cargo.lock"]);
+        assert!(args.disk_impact && !args.registry_cache);
+
+        let args = parse(&["--config-profile", "minimal"]);
+        assert!(args.total_bytes);
+    }
+
+    #[test]
+    fn test_config_profile_reports_other_failures_under_its_name() {
+        let mut command = CargoCli::command();
+        command.build();
+        let broken = [
+            preset("top_files", &["--top-files", "many"]),
+            preset("as_percentage_of_binary", &["--as-percentage-of-binary"]),
+        ];
+        for preset in broken {
+            let err = expand(&command, argv(&[]), &[], std::slice::from_ref(&preset))
+                .map_err(|err| profile_error(&mut command, "disk", &err))
+                .unwrap_err();
+            let message = err.to_string();
+            assert!(
+                message.contains(&format!(
+                    "`--config-profile disk` adds `{}`",
+                    preset.flags.join(" ")
+                )),
+                "{}",
+                message
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::cli::DepsizeArgs;
+use crate::report::PackageReport;
+use crate::walk::{measure_packages, Measured, WalkOptions};
+use anyhow::{Context, Result};
+use cargo::GlobalContext;
+use semver::Version;
+use std::path::{Path, PathBuf};
+
+/// A crate unpacked in the registry cache, as found on disk.
+struct CachedCrate {
+    name: String,
+    version: String,
+    /// The `registry/src/<index>` directory it was unpacked into.
+    index: String,
+    path: PathBuf,
+}
+
+/// Measures every crate unpacked under `$CARGO_HOME/registry/src`, whichever
+/// project it was downloaded for.
+///
+/// Returns the measured packages and whether the scan was interrupted.
+pub async fn measure_registry_cache(
+    config: &GlobalContext,
+    args: &DepsizeArgs,
+) -> Result<(Vec<PackageReport>, bool)> {
+    let src_dir = config
+        .home()
+        .as_path_unlocked()
+        .join("registry")
+        .join("src");
+    let crates = find_cached_crates(&src_dir)?;
+
+    let packages = crates
+        .iter()
+        .enumerate()
+        .map(|(i, krate)| {
+            let label = format!("{} v{} ({})", krate.name, krate.version, krate.index);
+            (i, label, krate.path.clone())
+        })
+        .collect();
+    let Measured { sizes, interrupted } =
+        measure_packages(packages, WalkOptions::from_args(args)).await?;
+
+    let package_infos = crates
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, krate)| {
+            let size = sizes.get(&i)?;
+            let mut info = PackageReport {
+                name: krate.name,
+                version: krate.version,
+                bytes: size.bytes,
+                files: size.files,
+                source: krate.index,
+                path: args.show_path.then(|| krate.path.clone()),
+                dirs: args.breakdown_dirs.then_some(size.dirs),
+                largest_files: size.largest_files(),
+                root: krate.path,
+                ..Default::default()
+            };
+            if args.disk_impact {
+                info.add_archive_size();
+            }
+            Some(info)
+        })
+        .collect();
+
+    Ok((package_infos, interrupted))
+}
+
+/// Lists the `<index>/<name>-<version>` directories under `src_dir`.
+fn find_cached_crates(src_dir: &Path) -> Result<Vec<CachedCrate>> {
+    let mut crates = Vec::new();
+    if !src_dir.exists() {
+        return Ok(crates);
+    }
+
+    let read_dir = |dir: &Path| {
+        std::fs::read_dir(dir).with_context(|| format!("failed to list {}", dir.display()))
+    };
+    for index in read_dir(src_dir)? {
+        let index = index?;
+        if !index.file_type()?.is_dir() {
+            continue;
+        }
+        let index_name = index.file_name().to_string_lossy().into_owned();
+        for entry in read_dir(&index.path())? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let dir_name = entry.file_name().to_string_lossy().into_owned();
+            let Some((name, version)) = split_name_version(&dir_name) else {
+                continue;
+            };
+            crates.push(CachedCrate {
+                name: name.to_string(),
+                version: version.to_string(),
+                index: index_name.clone(),
+                path: entry.path(),
+            });
+        }
+    }
+
+    Ok(crates)
+}
+
+/// Splits a `<name>-<version>` directory name. Both halves may contain
+/// dashes, so the split is at the first dash followed by a valid version.
+fn split_name_version(dir_name: &str) -> Option<(&str, &str)> {
+    dir_name
+        .match_indices('-')
+        .map(|(i, _)| (&dir_name[..i], &dir_name[i + 1..]))
+        .find(|(_, version)| Version::parse(version).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_name_version() {
+        assert_eq!(
+            split_name_version("serde-1.0.200"),
+            Some(("serde", "1.0.200"))
+        );
+        assert_eq!(
+            split_name_version("proc-macro2-1.0.86"),
+            Some(("proc-macro2", "1.0.86"))
+        );
+        assert_eq!(
+            split_name_version("windows-sys-0.52.0-rc.1"),
+            Some(("windows-sys", "0.52.0-rc.1"))
+        );
+        assert_eq!(split_name_version("not-a-crate"), None);
+    }
+}
@@ -0,0 +1,1339 @@
+use crate::checksum::archive_path;
+use crate::cli::{Column, DepsizeArgs, Rounding, SortDirection, SortKey, SumBy, Unit};
+use crate::compare::{Comparison, FileDiff};
+use crate::group::{Group, Grouping, UNKNOWN};
+use crate::saturating;
+use crate::walk::{DirBreakdown, PackageSize};
+use cargo::core::{Package, PackageId};
+use serde::Serialize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::path::PathBuf;
+use unicode_width::UnicodeWidthStr;
+
+/// The size of a single dependency package, as shown in the report.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PackageReport {
+    pub name: String,
+    pub version: String,
+    pub bytes: u64,
+    pub files: u64,
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The directory the package was measured in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dirs: Option<DirBreakdown>,
+    /// With `--with-artifacts`, the size of its compiled `.rlib`, `.rmeta`
+    /// and shared libraries in the target directory, 0 if it wasn't built.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_bytes: Option<u64>,
+    /// With `--as-percentage-of-binary`, `bytes` as a percentage of
+    /// `artifact_bytes`, if it was built.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_percent_of_artifacts: Option<f64>,
+    /// With `--compile-time`, how many seconds it took to compile, 0 if it
+    /// wasn't built.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compile_seconds: Option<f64>,
+    /// With `--metadata-overhead`, how much of `bytes` is the manifest and
+    /// README rather than code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata_bytes: Option<u64>,
+    /// With `--estimate-install-time`, roughly how many seconds a cold fetch
+    /// and unpack of the package takes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_seconds: Option<f64>,
+    /// With `--disk-impact`, the size of the cached `.crate` archive, which is
+    /// included in `bytes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_bytes: Option<u64>,
+    /// With `--dedupe-kinds`, every kind of dependency it is (`normal`, `dev`
+    /// or `build`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kinds: Option<Vec<&'static str>>,
+    /// With `--record-files`, the size of every file by package-relative path,
+    /// with `/` separators.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_sizes: Option<BTreeMap<String, u64>>,
+    /// Whether a `[patch]` section replaced the package's usual source.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub patched: bool,
+    /// Whether the version has been yanked from its registry.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub yanked: bool,
+    #[serde(skip)]
+    pub proc_macro: bool,
+    /// Whether to show `source` in the listing, for a name shared with a
+    /// workspace member.
+    #[serde(skip)]
+    pub show_source: bool,
+    /// With `--strip-prerelease`, show the version without its prerelease
+    /// and build metadata.
+    #[serde(skip)]
+    pub strip_prerelease: bool,
+    /// The `--group-by` keys of this package, more than one with
+    /// `--author-attribution each`.
+    #[serde(skip)]
+    pub groups: Vec<String>,
+    /// With `--top-files`, its largest files as `(bytes, package-relative path)`.
+    #[serde(skip)]
+    pub largest_files: Vec<(u64, PathBuf)>,
+    /// The directory the package was measured in, for `--absolute-paths`.
+    #[serde(skip)]
+    pub root: PathBuf,
+    /// With `--sample-rate`, the variance of the estimated `bytes`.
+    #[serde(skip)]
+    pub estimate_variance: f64,
+}
+
+impl PackageReport {
+    pub fn new(package: &Package, size: &PackageSize) -> Self {
+        PackageReport {
+            name: package.name().to_string(),
+            version: package.version().to_string(),
+            bytes: size.bytes,
+            files: size.files,
+            source: source_label(package.package_id()),
+            proc_macro: package.targets().iter().any(|target| target.proc_macro()),
+            largest_files: size.largest_files(),
+            root: package.root().to_path_buf(),
+            estimate_variance: size.estimate_variance,
+            ..Default::default()
+        }
+    }
+
+    /// Adds the size of the `.crate` archive the package was unpacked from,
+    /// for `--disk-impact`. Packages without one (git and path dependencies,
+    /// or archives removed from the cache) get 0.
+    pub fn add_archive_size(&mut self) {
+        let archive_bytes = archive_path(&self.root)
+            .and_then(|archive| std::fs::metadata(archive).ok())
+            .map_or(0, |metadata| metadata.len());
+        saturating::add(&mut self.bytes, archive_bytes);
+        self.archive_bytes = Some(archive_bytes);
+    }
+
+    /// Estimates `install_seconds` for `--estimate-install-time`: downloading
+    /// the `.crate` archive at `bandwidth_mbps` megabits per second, or a
+    /// quarter of the sources without one, then unpacking each file. Path
+    /// dependencies are never fetched and take 0.
+    ///
+    /// Call before `bytes` and `files` are changed to cover more than the
+    /// package itself.
+    pub fn estimate_install_time(&mut self, bandwidth_mbps: f64) {
+        // Typical for gzipped Rust sources, and for extracting one file
+        const SOURCE_BYTES_PER_ARCHIVE_BYTE: u64 = 4;
+        const UNPACK_SECONDS_PER_FILE: f64 = 0.0005;
+
+        if self.source == "path" {
+            self.install_seconds = Some(0.0);
+            return;
+        }
+        let archive_bytes = archive_path(&self.root)
+            .and_then(|archive| std::fs::metadata(archive).ok())
+            .map_or(self.bytes / SOURCE_BYTES_PER_ARCHIVE_BYTE, |metadata| {
+                metadata.len()
+            });
+        let download_seconds = archive_bytes as f64 * 8.0 / (bandwidth_mbps * 1_000_000.0);
+        self.install_seconds = Some(download_seconds + self.files as f64 * UNPACK_SECONDS_PER_FILE);
+    }
+
+    /// Fills in `file_sizes` from the files kept while walking the package,
+    /// which are all of them with `--record-files`.
+    pub fn record_file_sizes(&mut self) {
+        self.file_sizes = Some(
+            self.largest_files
+                .iter()
+                .map(|(bytes, path)| {
+                    let components: Vec<_> = path
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect();
+                    (components.join("/"), *bytes)
+                })
+                .collect(),
+        );
+    }
+
+    /// The `name (vX.Y.Z)` label used in the human-readable listing.
+    pub fn name_ver(&self) -> String {
+        let mut label = format!("{} (v{})", self.name, self.display_version());
+        if self.show_source {
+            label.push_str(&format!(" ({})", self.source));
+        }
+        if self.patched {
+            label.push_str(" (patched)");
+        }
+        if self.yanked {
+            label.push_str(" (yanked)");
+        }
+        label
+    }
+
+    /// The version as shown in the listing, `X.Y.Z` with `--strip-prerelease`.
+    pub fn display_version(&self) -> &str {
+        if self.strip_prerelease {
+            strip_prerelease(&self.version)
+        } else {
+            &self.version
+        }
+    }
+}
+
+/// One `--format ndjson-stream` line, printed as soon as a listed package is
+/// measured.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StreamEntry {
+    pub name: String,
+    pub version: String,
+    pub bytes: u64,
+    pub file_count: u64,
+    pub scan_duration_ms: u128,
+}
+
+impl StreamEntry {
+    /// The line for `package`, with the `bytes` of its row in the report.
+    pub fn new(package: &Package, size: &PackageSize, args: &DepsizeArgs) -> Self {
+        let mut info = PackageReport::new(package, size);
+        if args.disk_impact {
+            info.add_archive_size();
+        }
+        StreamEntry {
+            name: info.name,
+            version: info.version,
+            bytes: info.bytes,
+            file_count: info.files,
+            scan_duration_ms: size.duration.as_millis(),
+        }
+    }
+
+    pub fn print(&self) -> serde_json::Result<()> {
+        outln!("{}", serde_json::to_string(self)?);
+        Ok(())
+    }
+}
+
+/// Several packages folded into a single summary row.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GroupTotal {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+impl GroupTotal {
+    pub fn add(&mut self, bytes: u64) {
+        self.count += 1;
+        saturating::add(&mut self.bytes, bytes);
+    }
+}
+
+/// How many of the measured packages are larger than a size, for
+/// `--summary-only-deps-over`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DepsOver {
+    pub threshold_bytes: u64,
+    pub count: usize,
+}
+
+/// The distribution of package sizes, for `--percentiles`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Percentiles {
+    pub p50_bytes: u64,
+    pub p90_bytes: u64,
+    pub p99_bytes: u64,
+    pub mean_bytes: u64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of `sizes`, so each one is the size of an
+    /// actual package; all 0 without any package.
+    pub fn new(mut sizes: Vec<u64>) -> Self {
+        if sizes.is_empty() {
+            return Percentiles::default();
+        }
+        sizes.sort_unstable();
+        let percentile = |p: usize| sizes[(p * sizes.len()).div_ceil(100).max(1) - 1];
+        let total: u128 = sizes.iter().map(|&bytes| bytes as u128).sum();
+        Percentiles {
+            p50_bytes: percentile(50),
+            p90_bytes: percentile(90),
+            p99_bytes: percentile(99),
+            mean_bytes: (total / sizes.len() as u128) as u64,
+        }
+    }
+}
+
+/// How rough the sizes are with `--sample-rate`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Estimate {
+    pub sample_rate: f64,
+    /// Half the width of the 95% confidence interval of `total_bytes`, if
+    /// every package had at least two files sampled to tell it from.
+    pub margin_bytes: Option<u64>,
+}
+
+impl Estimate {
+    /// Sums the variances of independently estimated packages.
+    pub fn new(sample_rate: f64, variances: impl IntoIterator<Item = f64>) -> Self {
+        let variance: f64 = variances.into_iter().sum();
+        Estimate {
+            sample_rate,
+            margin_bytes: variance
+                .is_finite()
+                .then(|| (1.96 * variance.sqrt()).round() as u64),
+        }
+    }
+}
+
+/// A shields.io endpoint badge of the total size, for `--badge`.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Badge {
+    pub schema_version: u8,
+    pub label: &'static str,
+    pub message: String,
+    pub color: &'static str,
+}
+
+impl Badge {
+    /// Green below `--badge-warn-size`, orange below `--badge-error-size` and
+    /// red from there on. The message is the size without its byte count.
+    pub fn new(total_bytes: u64, args: &DepsizeArgs) -> Self {
+        let size = format_size(total_bytes, args.size_format());
+        let message = match size.split_once(" (") {
+            Some((rounded, _)) => rounded.to_string(),
+            None => size,
+        };
+        let color = if total_bytes >= args.badge_error_size {
+            "red"
+        } else if total_bytes >= args.badge_warn_size {
+            "orange"
+        } else {
+            "green"
+        };
+        Badge {
+            schema_version: 1,
+            label: "deps",
+            message,
+            color,
+        }
+    }
+}
+
+/// One of the largest files across all listed packages.
+#[derive(Debug, Clone, Serialize)]
+pub struct LargeFile {
+    /// `name vX.Y.Z` of the package the file belongs to.
+    pub package: String,
+    /// Relative to the package root, unless `--absolute-paths` is given.
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// One size range of a [`Report::histogram`].
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramBucket {
+    pub label: &'static str,
+    /// Exclusive upper bound in bytes, `None` for the last bucket.
+    pub max_bytes: Option<u64>,
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// The full result of a depsize run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub packages: Vec<PackageReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proc_macros: Option<GroupTotal>,
+    /// The packages smaller than `--collapse-under`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collapsed: Option<GroupTotal>,
+    /// The project's own package, measured with `--with-self`. It is not part
+    /// of `total_bytes`.
+    #[serde(rename = "self", skip_serializing_if = "Option::is_none")]
+    pub self_package: Option<PackageReport>,
+    /// Resolved packages named by `--deny-crate` or `--deny-file`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub denied: Vec<PackageReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<Vec<HistogramBucket>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grouping: Option<Grouping>,
+    /// The largest files across all listed packages, largest first.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_files: Option<Vec<LargeFile>>,
+    /// The versions of every package listed by `--only-duplicates`, per name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicates: Option<Vec<Group>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Comparison>,
+    /// With `--diff-files`, how the files of one package changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_diff: Option<FileDiff>,
+    /// Counted over every measured package, including hidden ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deps_over: Option<DepsOver>,
+    /// The size distribution over every measured package, including hidden ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentiles: Option<Percentiles>,
+    /// With `--metadata-overhead`, the metadata files summed over every
+    /// measured package, including hidden ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata_overhead: Option<GroupTotal>,
+    /// With `--estimate-install-time`, the cold install cost summed over
+    /// every measured package, including hidden ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_seconds: Option<f64>,
+    /// With `--sample-rate`, how far off the extrapolated sizes may be.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<Estimate>,
+    /// Whether the run was cut short by Ctrl-C, leaving some packages unmeasured.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+    /// Number of packages counted in `total_bytes`, including hidden ones.
+    pub package_count: usize,
+    pub total_bytes: u64,
+    /// The `--sum-by` model `total_bytes` follows.
+    pub sum_by: &'static str,
+}
+
+/// The aggregate part of a [`Report`], without the per-package listing.
+#[derive(Debug, Serialize)]
+pub struct Summary<'a> {
+    pub total_bytes: u64,
+    pub package_count: usize,
+    /// The five largest listed packages, largest first.
+    pub top: Vec<&'a PackageReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deps_over: Option<DepsOver>,
+}
+
+impl Report {
+    /// Prints the aligned, human-readable table followed by the total line.
+    ///
+    /// Without `--columns` or `--format-template` each row is the classic
+    /// `name (vX.Y.Z) : size` line.
+    pub fn print_human(&self, args: &DepsizeArgs) {
+        let size_format = args.size_format();
+        if self.interrupted {
+            outln!("(interrupted, partial results)");
+        }
+
+        if let Some(file_diff) = &self.file_diff {
+            file_diff.print_human(size_format);
+            return;
+        }
+
+        if let Some(comparison) = &self.comparison {
+            comparison.print_human(size_format, args.relative_change_only);
+            return;
+        }
+
+        if let Some(histogram) = &self.histogram {
+            let width = name_width(
+                args,
+                histogram.iter().map(|bucket| bucket.label.to_string()),
+            );
+            print_histogram(histogram, width);
+            self.print_total(args);
+            return;
+        }
+
+        if self.grouping.is_some() || self.duplicates.is_some() {
+            if let Some(grouping) = &self.grouping {
+                let width = name_width(args, grouping.groups.iter().map(|group| group.key.clone()));
+                print_grouping(grouping, size_format, width);
+            }
+            if let Some(duplicates) = &self.duplicates {
+                if self.grouping.is_some() {
+                    outln!();
+                }
+                let width = name_width(args, duplicates.iter().map(|group| group.key.clone()));
+                let show_source = args
+                    .columns
+                    .as_ref()
+                    .is_some_and(|columns| columns.contains(&Column::Source));
+                self.print_duplicates(duplicates, size_format, width, show_source);
+            }
+            self.print_total(args);
+            return;
+        }
+
+        let collapsed_label = self
+            .collapsed
+            .as_ref()
+            .filter(|collapsed| collapsed.count > 0)
+            .map(|collapsed| format!("({} smaller crates)", collapsed.count));
+        let width = name_width(
+            args,
+            self.packages
+                .iter()
+                .map(PackageReport::name_ver)
+                .chain(collapsed_label.clone())
+                .chain(
+                    self.proc_macros
+                        .as_ref()
+                        .map(|_| "[proc-macros]".to_string()),
+                )
+                .chain(self.self_package.as_ref().map(|_| "[self]".to_string())),
+        );
+
+        let lines = match (&args.columns, &args.format_template) {
+            (_, Some(template)) => self
+                .packages
+                .iter()
+                .map(|package| {
+                    template.render(package, self.percent_of_total(package.bytes), size_format)
+                })
+                .collect(),
+            (Some(columns), None) => self.column_lines(columns, size_format),
+            (None, None) => self
+                .packages
+                .iter()
+                .map(|package| {
+                    format!(
+                        "{} : {}",
+                        pad(&package.name_ver(), width),
+                        format_size(package.bytes, size_format)
+                    )
+                })
+                .collect(),
+        };
+
+        let color = args.color.enabled();
+        let shown = args.max_output_lines.unwrap_or(usize::MAX);
+        for (package, line) in self.packages.iter().zip(lines).take(shown) {
+            let mut line = line;
+            if let Some(url) = &package.url {
+                line = format!("{}  {}", line, url);
+            }
+            if let Some(path) = &package.path {
+                line = format!("{}  {}", line, path.display());
+            }
+            if let Some(archive_bytes) = package.archive_bytes {
+                line = format!(
+                    "{}  (incl. {} archive)",
+                    line,
+                    format_size(archive_bytes, size_format)
+                );
+            }
+            if let Some(kinds) = &package.kinds {
+                line = format!("{}  [{}]", line, kinds.join(", "));
+            }
+            if let Some(artifact_bytes) = package.artifact_bytes {
+                let artifacts = match artifact_bytes {
+                    0 => "not built".to_string(),
+                    bytes => format_size(bytes, size_format),
+                };
+                line = match package.source_percent_of_artifacts {
+                    Some(pct) => format!(
+                        "{}  [artifacts: {}, source is {:.0}% of that]",
+                        line, artifacts, pct
+                    ),
+                    None => format!("{}  [artifacts: {}]", line, artifacts),
+                };
+            }
+            if let Some(metadata_bytes) = package.metadata_bytes {
+                line = format!(
+                    "{}  [metadata: {}]",
+                    line,
+                    format_size(metadata_bytes, size_format)
+                );
+            }
+            if let Some(seconds) = package.install_seconds {
+                line = format!("{}  [install: ~{:.2}s]", line, seconds);
+            }
+            if let Some(seconds) = package.compile_seconds {
+                line = match seconds {
+                    0.0 => format!("{}  [compile: not built]", line),
+                    seconds => format!("{}  [compile: {:.2}s]", line, seconds),
+                };
+            }
+            if color {
+                line = colorize(&line, package.bytes, args);
+            }
+            outln!("{}", line);
+
+            if let Some(dirs) = &package.dirs {
+                for (label, bytes) in dirs.buckets() {
+                    outln!(
+                        "    {} : {}",
+                        pad(label, width.saturating_sub(4)),
+                        format_size(bytes, size_format)
+                    );
+                }
+            }
+        }
+        if let Some(hidden) = self
+            .packages
+            .get(shown..)
+            .filter(|hidden| !hidden.is_empty())
+        {
+            outln!(
+                "... and {} more dependencies, {} in total",
+                hidden.len(),
+                format_size(
+                    saturating::sum(hidden.iter().map(|package| package.bytes)),
+                    size_format
+                )
+            );
+        }
+
+        if let (Some(collapsed), Some(label)) = (&self.collapsed, &collapsed_label) {
+            outln!(
+                "{} : {}",
+                pad(label, width),
+                format_size(collapsed.bytes, size_format)
+            );
+        }
+        if let Some(proc_macros) = &self.proc_macros {
+            outln!(
+                "{} : {} across {} crates",
+                pad("[proc-macros]", width),
+                format_size(proc_macros.bytes, size_format),
+                proc_macros.count
+            );
+        }
+        if let Some(self_package) = &self.self_package {
+            outln!(
+                "{} : {}",
+                pad("[self]", width),
+                format_size(self_package.bytes, size_format)
+            );
+        }
+
+        self.print_total(args);
+        if let Some(self_package) = &self.self_package {
+            let total = self.total_bytes + self_package.bytes;
+            let pct = if total == 0 {
+                0.0
+            } else {
+                self_package.bytes as f64 / total as f64 * 100.0
+            };
+            outln!("self: {:.2}% of total", pct);
+        }
+
+        if let Some(top_files) = &self.top_files {
+            outln!();
+            outln!("Largest files:");
+            let width = name_width(args, top_files.iter().map(|file| file.package.clone()));
+            for file in top_files {
+                outln!(
+                    "{} : {}  {}",
+                    pad(&file.package, width),
+                    format_size(file.bytes, size_format),
+                    file.path.display()
+                );
+            }
+        }
+    }
+
+    /// The `limit` largest files of all listed packages, largest first.
+    ///
+    /// Paths are relative to their package unless `absolute` is set.
+    pub fn top_files(&self, limit: usize, absolute: bool) -> Vec<LargeFile> {
+        // A min-heap of the largest seen so far, so the smallest is dropped first
+        let mut top = BinaryHeap::new();
+        for package in &self.packages {
+            for (bytes, path) in &package.largest_files {
+                top.push(Reverse((
+                    *bytes,
+                    &package.name,
+                    &package.version,
+                    path,
+                    &package.root,
+                )));
+                if top.len() > limit {
+                    top.pop();
+                }
+            }
+        }
+
+        top.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((bytes, name, version, path, root))| LargeFile {
+                package: format!("{} v{}", name, version),
+                path: if absolute {
+                    root.join(path)
+                } else {
+                    path.clone()
+                },
+                bytes,
+            })
+            .collect()
+    }
+
+    /// Prints the `> Total size:` line, unless `--no-total` was given, and
+    /// the `--summary-only-deps-over` count, percentiles and metadata
+    /// overhead.
+    fn print_total(&self, args: &DepsizeArgs) {
+        if !args.no_total {
+            match args.sum_by {
+                SumBy::Direct => outln!(
+                    "> Total size: {}",
+                    format_size(self.total_bytes, args.size_format())
+                ),
+                sum_by => outln!(
+                    "> Total size: {}, summed by {}",
+                    format_size(self.total_bytes, args.size_format()),
+                    sum_by.label()
+                ),
+            }
+        }
+        if let Some(seconds) = self.install_seconds {
+            outln!(
+                "> Estimated cold install: ~{:.1}s at {} Mbit/s (experimental)",
+                seconds,
+                args.bandwidth
+            );
+        }
+        if let Some(estimate) = &self.estimate {
+            match estimate.margin_bytes {
+                Some(margin_bytes) => outln!(
+                    "> Estimated from {:.0}% of the files: the total is within ±{} at 95% confidence",
+                    estimate.sample_rate * 100.0,
+                    format_size(margin_bytes, args.size_format())
+                ),
+                None => outln!(
+                    "> Estimated from {:.0}% of the files: too few were sampled to tell how close the total is",
+                    estimate.sample_rate * 100.0
+                ),
+            }
+        }
+        if let Some(deps_over) = &self.deps_over {
+            outln!(
+                "> {} of {} dependencies are over {}",
+                deps_over.count,
+                self.package_count,
+                format_size(deps_over.threshold_bytes, args.size_format())
+            );
+        }
+        if let Some(percentiles) = &self.percentiles {
+            let size_format = args.size_format();
+            outln!(
+                "> p50: {}, p90: {}, p99: {}, mean: {}",
+                format_size(percentiles.p50_bytes, size_format),
+                format_size(percentiles.p90_bytes, size_format),
+                format_size(percentiles.p99_bytes, size_format),
+                format_size(percentiles.mean_bytes, size_format)
+            );
+        }
+        if let Some(overhead) = &self.metadata_overhead {
+            let percent = match self.total_bytes {
+                0 => 0.0,
+                total => overhead.bytes as f64 / total as f64 * 100.0,
+            };
+            outln!(
+                "> Metadata overhead: {} across {} packages, {:.1}% of the total",
+                format_size(overhead.bytes, args.size_format()),
+                overhead.count,
+                percent
+            );
+        }
+    }
+
+    /// Prints the combined size of each duplicated name, then its versions,
+    /// each with its source if `show_source`.
+    fn print_duplicates(
+        &self,
+        duplicates: &[Group],
+        size_format: SizeFormat,
+        width: usize,
+        show_source: bool,
+    ) {
+        for group in duplicates {
+            outln!(
+                "{} : {} across {} versions",
+                pad(&group.key, width),
+                format_size(group.bytes, size_format),
+                group.count
+            );
+            for package in self
+                .packages
+                .iter()
+                .filter(|package| package.name == group.key)
+            {
+                let source = if show_source {
+                    format!(" ({})", package.source)
+                } else {
+                    String::new()
+                };
+                outln!(
+                    "    {} : {}{}",
+                    pad(&format!("v{}", package.version), width.saturating_sub(4)),
+                    format_size(package.bytes, size_format),
+                    source
+                );
+            }
+        }
+    }
+
+    /// Renders each package as the chosen columns, padded to line up.
+    fn column_lines(&self, columns: &[Column], size_format: SizeFormat) -> Vec<String> {
+        let rows: Vec<Vec<String>> = self
+            .packages
+            .iter()
+            .map(|package| {
+                columns
+                    .iter()
+                    .map(|column| match column {
+                        Column::Name => package.name.clone(),
+                        Column::Version => format!("v{}", package.display_version()),
+                        Column::Size => format_size(package.bytes, size_format),
+                        Column::Files => format!("{} files", package.files),
+                        Column::Percent => format!("{:.2}%", self.percent_of_total(package.bytes)),
+                        Column::Source => package.source.clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut widths = vec![0; columns.len()];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.width());
+            }
+        }
+
+        rows.iter()
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| pad(cell, *width))
+                    .collect();
+                cells.join("  ").trim_end().to_string()
+            })
+            .collect()
+    }
+
+    /// Counts `packages` per size range: under 10KB, 10KB-100KB, 100KB-1MB
+    /// and over 1MB.
+    pub fn histogram(packages: &[PackageReport]) -> Vec<HistogramBucket> {
+        const KB: u64 = 1024;
+        const MB: u64 = KB * 1024;
+
+        let mut buckets = [
+            ("< 10KB", Some(10 * KB)),
+            ("10KB - 100KB", Some(100 * KB)),
+            ("100KB - 1MB", Some(MB)),
+            (">= 1MB", None),
+        ]
+        .map(|(label, max_bytes)| HistogramBucket {
+            label,
+            max_bytes,
+            count: 0,
+            bytes: 0,
+        });
+
+        for package in packages {
+            let bucket = buckets
+                .iter_mut()
+                .find(|bucket| bucket.max_bytes.is_none_or(|max| package.bytes < max))
+                .expect("the last bucket is unbounded");
+            bucket.count += 1;
+            saturating::add(&mut bucket.bytes, package.bytes);
+        }
+
+        buckets.to_vec()
+    }
+
+    /// Share of the total size, in percent.
+    pub fn percent_of_total(&self, bytes: u64) -> f64 {
+        if self.total_bytes == 0 {
+            0.0
+        } else {
+            bytes as f64 / self.total_bytes as f64 * 100.0
+        }
+    }
+
+    pub fn summary(&self) -> Summary<'_> {
+        let mut top: Vec<&PackageReport> = self.packages.iter().collect();
+        top.sort_by_key(|package| Reverse(package.bytes));
+        top.truncate(5);
+
+        Summary {
+            total_bytes: self.total_bytes,
+            package_count: self.package_count,
+            top,
+            deps_over: self.deps_over,
+        }
+    }
+
+    /// Prints the report as a single JSON object, on one line unless `pretty`.
+    pub fn print_json(&self, pretty: bool) -> serde_json::Result<()> {
+        if pretty {
+            outln!("{}", serde_json::to_string_pretty(self)?);
+        } else {
+            outln!("{}", serde_json::to_string(self)?);
+        }
+        Ok(())
+    }
+
+    /// Prints the listed packages as `[[dependency]]` tables after the total.
+    pub fn print_toml(&self) -> Result<(), toml::ser::Error> {
+        #[derive(Serialize)]
+        struct TomlReport<'a> {
+            total_bytes: u64,
+            dependency: Vec<TomlDependency<'a>>,
+        }
+
+        #[derive(Serialize)]
+        struct TomlDependency<'a> {
+            name: &'a str,
+            version: &'a str,
+            bytes: u64,
+        }
+
+        let report = TomlReport {
+            total_bytes: self.total_bytes,
+            dependency: self
+                .packages
+                .iter()
+                .map(|package| TomlDependency {
+                    name: &package.name,
+                    version: &package.version,
+                    bytes: package.bytes,
+                })
+                .collect(),
+        };
+        out!("{}", toml::to_string(&report)?);
+        Ok(())
+    }
+}
+
+/// Orders the listing by `sort_key`, in `direction`, breaking ties by
+/// `secondary` in its default direction.
+///
+/// Packages are first put in `name`, `version`, `source` order so that the
+/// remaining ties come out the same way on every run; the order they were
+/// measured or resolved in is otherwise arbitrary.
+pub fn sort_packages(
+    packages: &mut [PackageReport],
+    sort_key: SortKey,
+    direction: SortDirection,
+    secondary: SortKey,
+) {
+    packages
+        .sort_by(|a, b| (&a.name, &a.version, &a.source).cmp(&(&b.name, &b.version, &b.source)));
+
+    // `sort_by` is stable, so each sort only reorders the ties of the next one
+    sort_by_key(packages, secondary, secondary.default_direction());
+    sort_by_key(packages, sort_key, direction);
+}
+
+fn sort_by_key(packages: &mut [PackageReport], sort_key: SortKey, direction: SortDirection) {
+    let compare = |a: &PackageReport, b: &PackageReport| match sort_key {
+        SortKey::Size => a.bytes.cmp(&b.bytes),
+        SortKey::Name => a.name.cmp(&b.name),
+        SortKey::Version => compare_versions(&a.version, &b.version),
+        SortKey::Files => a.files.cmp(&b.files),
+    };
+    match direction {
+        SortDirection::Asc => packages.sort_by(compare),
+        SortDirection::Desc => packages.sort_by(|a, b| compare(b, a)),
+    }
+}
+
+/// `1.0.0-alpha.3+build.99` without the `-alpha.3+build.99`.
+fn strip_prerelease(version: &str) -> &str {
+    version
+        .split_once(['-', '+'])
+        .map_or(version, |(release, _)| release)
+}
+
+/// Orders versions by semver precedence, so `1.10.0` comes after `1.9.0`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (semver::Version::parse(a), semver::Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// The width of the name column: `--name-width`, or with `--name-width 0`
+/// the display width of the longest of `labels`.
+fn name_width(args: &DepsizeArgs, labels: impl IntoIterator<Item = String>) -> usize {
+    match args.name_width {
+        0 => labels
+            .into_iter()
+            .map(|label| label.width())
+            .max()
+            .unwrap_or(0),
+        width => width,
+    }
+}
+
+/// Prints one subtotal line per group, then warns about the packages whose
+/// group is unknown.
+fn print_grouping(grouping: &Grouping, size_format: SizeFormat, width: usize) {
+    for group in &grouping.groups {
+        outln!(
+            "{} : {} across {} crates",
+            pad(&group.key, width),
+            format_size(group.bytes, size_format),
+            group.count
+        );
+    }
+
+    if let Some(unknown) = grouping.groups.iter().find(|group| group.key == UNKNOWN) {
+        eprintln!(
+            "warning: {} crates ({}) have no {} information, worth reviewing: {}",
+            unknown.count,
+            format_size(unknown.bytes, size_format),
+            grouping.field,
+            unknown.packages.join(", ")
+        );
+    }
+}
+
+/// Prints one line per bucket with its package count and a bar scaled to the
+/// fullest bucket.
+fn print_histogram(histogram: &[HistogramBucket], width: usize) {
+    const BAR_WIDTH: usize = 40;
+    let max_count = histogram
+        .iter()
+        .map(|bucket| bucket.count)
+        .max()
+        .unwrap_or(0);
+
+    for bucket in histogram {
+        let bar_len = if max_count == 0 {
+            0
+        } else {
+            (bucket.count * BAR_WIDTH).div_ceil(max_count)
+        };
+        let line = format!(
+            "{} : {: >4} {}",
+            pad(bucket.label, width),
+            bucket.count,
+            "#".repeat(bar_len)
+        );
+        outln!("{}", line.trim_end());
+    }
+}
+
+/// Wraps a listing line in red from `--error-size`, yellow from
+/// `--warn-size` and green below that.
+fn colorize(line: &str, bytes: u64, args: &DepsizeArgs) -> String {
+    const RED: &str = "\x1b[31m";
+    const YELLOW: &str = "\x1b[33m";
+    const GREEN: &str = "\x1b[32m";
+    const RESET: &str = "\x1b[0m";
+
+    let color = if bytes >= args.error_size {
+        RED
+    } else if bytes >= args.warn_size {
+        YELLOW
+    } else {
+        GREEN
+    };
+    format!("{}{}{}", color, line, RESET)
+}
+
+/// Returns a link to where a package comes from.
+///
+/// Crates.io packages link to their page on crates.io, everything else
+/// (git repositories, alternative registries, local paths) links to the
+/// URL of its source.
+pub fn source_url(package_id: PackageId) -> String {
+    let source_id = package_id.source_id();
+    if source_id.is_crates_io() {
+        format!(
+            "https://crates.io/crates/{}/{}",
+            package_id.name(),
+            package_id.version()
+        )
+    } else {
+        source_id.url().to_string()
+    }
+}
+
+/// Returns a short description of where a package comes from: `crates.io`,
+/// the name of an alternative registry, a git URL or `path`.
+pub fn source_label(package_id: PackageId) -> String {
+    let source_id = package_id.source_id();
+    if source_id.is_crates_io() {
+        "crates.io".to_string()
+    } else if source_id.is_registry() {
+        source_id.display_registry_name()
+    } else if source_id.is_path() {
+        "path".to_string()
+    } else {
+        source_id.url().to_string()
+    }
+}
+
+/// How [`format_size`] renders sizes, from `--rounding` and `--min-unit`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeFormat {
+    pub rounding: Rounding,
+    /// The smallest unit to show, so small sizes don't drop down to KB or bytes.
+    pub min_unit: Option<Unit>,
+}
+
+/// Left-aligns `s` in a column `width` terminal cells wide.
+///
+/// Unlike `{: <width$}`, which counts chars, this counts display width, so
+/// rows containing wide or combining characters still line up.
+pub fn pad(s: &str, width: usize) -> String {
+    let padding = width.saturating_sub(s.width());
+    format!("{}{}", s, " ".repeat(padding))
+}
+
+/// Formats a size value in bytes as a human-readable string with units of KB, MB, or GB.
+///
+/// # Arguments
+///
+/// * `size` - The size value in bytes to format.
+/// * `size_format` - How the unit value is rounded to two decimals and the
+///   smallest unit to use; the byte count is always exact.
+///
+/// # Returns
+///
+/// Returns a `String` containing the formatted size value with units and byte count.
+///
+/// # Example
+///
+/// ```
+/// let size_format = SizeFormat::default();
+/// assert_eq!(format_size(1024, size_format), "1.00KB (1024 bytes)");
+/// assert_eq!(format_size(1048576, size_format), "1.00MB (1048576 bytes)");
+/// assert_eq!(format_size(1073741824, size_format), "1.00GB (1073741824 bytes)");
+/// assert_eq!(format_size(100, size_format), "100 bytes");
+/// ```
+pub fn format_size(size: u64, size_format: SizeFormat) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+
+    let min_unit = match size_format.min_unit {
+        None => 0,
+        Some(Unit::Kb) => KB,
+        Some(Unit::Mb) => MB,
+    };
+    let unit_value = |unit: u64| size_format.rounding.apply(size as f64 / unit as f64);
+    if size >= GB {
+        format!("{:.2}GB ({:?} bytes)", unit_value(GB), size)
+    } else if size >= MB || min_unit == MB {
+        format!("{:.2}MB ({:?} bytes)", unit_value(MB), size)
+    } else if size >= KB || min_unit == KB {
+        format!("{:.2}KB ({:?} bytes)", unit_value(KB), size)
+    } else {
+        format!("{:?} bytes", size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CargoCli;
+    use clap::Parser;
+
+    fn package(name: &str, version: &str, bytes: u64) -> PackageReport {
+        PackageReport {
+            name: name.to_string(),
+            version: version.to_string(),
+            bytes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sort_packages_is_deterministic() {
+        let packages = vec![
+            package("b", "1.0.0", 10),
+            package("a", "2.0.0", 10),
+            package("a", "1.0.0", 10),
+            package("c", "1.0.0", 5),
+        ];
+
+        // Every rotation of the input, standing in for arbitrary measuring order
+        let orders: Vec<Vec<(String, String)>> = (0..packages.len())
+            .map(|shift| {
+                let mut packages = packages.clone();
+                packages.rotate_left(shift);
+                sort_packages(
+                    &mut packages,
+                    SortKey::Size,
+                    SortDirection::Asc,
+                    SortKey::Name,
+                );
+                packages
+                    .into_iter()
+                    .map(|package| (package.name, package.version))
+                    .collect()
+            })
+            .collect();
+
+        let expected = [
+            ("c", "1.0.0"),
+            ("a", "1.0.0"),
+            ("a", "2.0.0"),
+            ("b", "1.0.0"),
+        ]
+        .map(|(name, version)| (name.to_string(), version.to_string()));
+        for order in orders {
+            assert_eq!(order, expected);
+        }
+    }
+
+    #[test]
+    fn test_sort_packages_by_version_descending() {
+        let mut packages = vec![
+            package("a", "1.9.0", 10),
+            package("a", "1.10.0", 5),
+            package("b", "1.10.0", 20),
+        ];
+        sort_packages(
+            &mut packages,
+            SortKey::Version,
+            SortDirection::Desc,
+            SortKey::Name,
+        );
+        let order: Vec<(&str, &str)> = packages
+            .iter()
+            .map(|package| (package.name.as_str(), package.version.as_str()))
+            .collect();
+        // Equal versions stay in name order
+        assert_eq!(order, [("a", "1.10.0"), ("b", "1.10.0"), ("a", "1.9.0")]);
+
+        // ...or go by size with `--sort-secondary size`
+        sort_packages(
+            &mut packages,
+            SortKey::Version,
+            SortDirection::Desc,
+            SortKey::Size,
+        );
+        let order: Vec<(&str, u64)> = packages
+            .iter()
+            .map(|package| (package.name.as_str(), package.bytes))
+            .collect();
+        assert_eq!(order, [("a", 5), ("b", 20), ("a", 10)]);
+    }
+
+    #[test]
+    fn test_badge_colors_the_total_by_threshold() {
+        let CargoCli::Depsize(args) = CargoCli::parse_from([
+            "cargo",
+            "depsize",
+            "--badge",
+            "--badge-warn-size",
+            "1MB",
+            "--badge-error-size",
+            "2MB",
+        ]);
+        let badge = Badge::new(1024 * 1024 + 512 * 1024, &args);
+        assert_eq!(badge.message, "1.50MB");
+        assert_eq!(badge.color, "orange");
+        assert_eq!(Badge::new(100, &args).message, "100 bytes");
+        assert_eq!(Badge::new(100, &args).color, "green");
+        assert_eq!(Badge::new(2 * 1024 * 1024, &args).color, "red");
+        assert_eq!(
+            serde_json::to_string(&Badge::new(100, &args)).unwrap(),
+            r#"{"schemaVersion":1,"label":"deps","message":"100 bytes","color":"green"}"#
+        );
+    }
+
+    #[test]
+    fn test_percentiles_use_the_nearest_rank() {
+        let percentiles = Percentiles::new((1..=100).rev().collect());
+        assert_eq!(percentiles.p50_bytes, 50);
+        assert_eq!(percentiles.p90_bytes, 90);
+        assert_eq!(percentiles.p99_bytes, 99);
+        assert_eq!(percentiles.mean_bytes, 50);
+
+        let percentiles = Percentiles::new(vec![10, 4_000_000]);
+        assert_eq!(percentiles.p50_bytes, 10);
+        assert_eq!(percentiles.p99_bytes, 4_000_000);
+        assert_eq!(Percentiles::new(Vec::new()).p99_bytes, 0);
+    }
+
+    #[test]
+    fn test_strip_prerelease() {
+        assert_eq!(strip_prerelease("1.0.0-alpha.3+build.99"), "1.0.0");
+        assert_eq!(strip_prerelease("1.0.0+build.99"), "1.0.0");
+        assert_eq!(strip_prerelease("1.0.0"), "1.0.0");
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(
+            format_size(1024, SizeFormat::default()),
+            "1.00KB (1024 bytes)"
+        );
+        assert_eq!(
+            format_size(1048576, SizeFormat::default()),
+            "1.00MB (1048576 bytes)"
+        );
+        assert_eq!(
+            format_size(1073741824, SizeFormat::default()),
+            "1.00GB (1073741824 bytes)"
+        );
+        assert_eq!(format_size(100, SizeFormat::default()), "100 bytes");
+    }
+
+    fn rounding(rounding: Rounding) -> SizeFormat {
+        SizeFormat {
+            rounding,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_format_size_rounding() {
+        // 1.0009765625KB
+        assert_eq!(
+            format_size(1025, SizeFormat::default()),
+            "1.00KB (1025 bytes)"
+        );
+        assert_eq!(
+            format_size(1025, rounding(Rounding::Up)),
+            "1.01KB (1025 bytes)"
+        );
+        // 1.9990234375KB
+        assert_eq!(
+            format_size(2047, SizeFormat::default()),
+            "2.00KB (2047 bytes)"
+        );
+        assert_eq!(
+            format_size(2047, rounding(Rounding::Down)),
+            "1.99KB (2047 bytes)"
+        );
+        assert_eq!(
+            format_size(2048, rounding(Rounding::Up)),
+            "2.00KB (2048 bytes)"
+        );
+    }
+
+    #[test]
+    fn test_format_size_min_unit() {
+        let min_unit = |unit| SizeFormat {
+            min_unit: Some(unit),
+            ..Default::default()
+        };
+        assert_eq!(format_size(100, min_unit(Unit::Kb)), "0.10KB (100 bytes)");
+        assert_eq!(
+            format_size(524288, min_unit(Unit::Mb)),
+            "0.50MB (524288 bytes)"
+        );
+        // A floor, larger sizes still move up
+        assert_eq!(
+            format_size(1073741824, min_unit(Unit::Kb)),
+            "1.00GB (1073741824 bytes)"
+        );
+    }
+
+    #[test]
+    fn test_pad_counts_display_width() {
+        assert_eq!(pad("serde", 8), "serde   ");
+        // Each of these takes two cells
+        assert_eq!(pad("日本", 8), "日本    ");
+        // A combining accent takes none
+        assert_eq!(pad("cafe\u{301}", 8), "cafe\u{301}    ");
+        assert_eq!(pad("too-long-name", 4), "too-long-name");
+    }
+}
@@ -0,0 +1,362 @@
+use crate::report::{format_size, Report, SizeFormat};
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::borrow::Cow;
+
+/// A `--output-template-file` template, rendered against the `--json` form
+/// of the whole [`Report`].
+///
+/// `{packages.0.name}` inserts a value by its dotted path, optionally through
+/// a filter: `{total_bytes | size}` formats bytes like the report does and
+/// `{dirs | json}` writes JSON. `{{ for package in packages }}` …
+/// `{{ endfor }}` repeats over an array, with `{@index}` and `{@last}`
+/// available inside, and `{{ if comparison }}` … `{{ else }}` … `{{ endif }}`
+/// tests whether a value is present and not `false`, 0 or empty. Fields the
+/// report left out render as nothing. Literal braces are written `\{` and
+/// `\}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportTemplate {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Value {
+        path: Vec<String>,
+        filter: Filter,
+    },
+    For {
+        name: String,
+        path: Vec<String>,
+        body: Vec<Node>,
+    },
+    If {
+        path: Vec<String>,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Filter {
+    None,
+    Size,
+    Json,
+}
+
+/// A piece of the template before blocks are matched up.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Value(String),
+    Tag(String),
+}
+
+/// The innermost `for` loops being rendered, with the item bound to their
+/// name, its index and whether it's the last one.
+struct Scope<'a> {
+    name: &'a str,
+    item: &'a Value,
+    index: usize,
+    last: bool,
+}
+
+impl ReportTemplate {
+    /// Reads and parses the template at `path`, for clap, so that a broken
+    /// template is reported before anything is measured.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let template = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path, err))?;
+        ReportTemplate::parse(&template).map_err(|err| format!("{:#}", err))
+    }
+
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut tokens = tokenize(template)?.into_iter();
+        let (nodes, end) = parse_nodes(&mut tokens)?;
+        if let Some(end) = end {
+            bail!("`{{{{ {} }}}}` without a matching block", end);
+        }
+        Ok(ReportTemplate { nodes })
+    }
+
+    pub fn render(&self, report: &Report, size_format: SizeFormat) -> Result<String> {
+        let root = serde_json::to_value(report)?;
+        let mut out = String::new();
+        render_nodes(&self.nodes, &root, &mut Vec::new(), size_format, &mut out)?;
+        Ok(out)
+    }
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some('{' | '}')) => text.extend(chars.next()),
+            '{' => {
+                let block = chars.peek() == Some(&'{');
+                if block {
+                    chars.next();
+                }
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') if !block => break,
+                        Some('}') if chars.peek() == Some(&'}') => {
+                            chars.next();
+                            break;
+                        }
+                        Some(c) => inner.push(c),
+                        None => bail!("unclosed `{{` before `{}`", inner.trim()),
+                    }
+                }
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                let inner = inner.trim().to_string();
+                tokens.push(if block {
+                    Token::Tag(inner)
+                } else {
+                    Token::Value(inner)
+                });
+            }
+            '}' => bail!("unmatched `}}`, write `\\}}` for a literal brace"),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+/// Parses nodes up to the end of the template or the first `endfor`,
+/// `else` or `endif` tag, which is returned for the caller to check.
+fn parse_nodes(tokens: &mut impl Iterator<Item = Token>) -> Result<(Vec<Node>, Option<String>)> {
+    let mut nodes = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Value(value) => {
+                let (path, filter) = match value.split_once('|') {
+                    Some((path, filter)) => (path.trim(), filter.trim()),
+                    None => (value.as_str(), ""),
+                };
+                let filter = match filter {
+                    "" => Filter::None,
+                    "size" => Filter::Size,
+                    "json" => Filter::Json,
+                    other => bail!("unknown filter `{}`, expected `size` or `json`", other),
+                };
+                nodes.push(Node::Value {
+                    path: parse_path(path)?,
+                    filter,
+                });
+            }
+            Token::Tag(tag) => {
+                let words: Vec<&str> = tag.split_whitespace().collect();
+                match words.as_slice() {
+                    ["for", name, "in", path] => {
+                        let (body, end) = parse_nodes(tokens)?;
+                        if end.as_deref() != Some("endfor") {
+                            bail!("`{{{{ {} }}}}` is never closed by `{{{{ endfor }}}}`", tag);
+                        }
+                        nodes.push(Node::For {
+                            name: name.to_string(),
+                            path: parse_path(path)?,
+                            body,
+                        });
+                    }
+                    ["if", path] => {
+                        let (then, mut end) = parse_nodes(tokens)?;
+                        let mut otherwise = Vec::new();
+                        if end.as_deref() == Some("else") {
+                            (otherwise, end) = parse_nodes(tokens)?;
+                        }
+                        if end.as_deref() != Some("endif") {
+                            bail!("`{{{{ {} }}}}` is never closed by `{{{{ endif }}}}`", tag);
+                        }
+                        nodes.push(Node::If {
+                            path: parse_path(path)?,
+                            then,
+                            otherwise,
+                        });
+                    }
+                    ["endfor"] | ["else"] | ["endif"] => return Ok((nodes, Some(tag))),
+                    _ => bail!(
+                        "unknown block `{{{{ {} }}}}`, expected for, if, else, endfor or endif",
+                        tag
+                    ),
+                }
+            }
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn parse_path(path: &str) -> Result<Vec<String>> {
+    let segments: Vec<String> = path.split('.').map(str::to_string).collect();
+    if segments.iter().any(String::is_empty) {
+        bail!("`{}` is not a field path such as `packages.0.name`", path);
+    }
+    Ok(segments)
+}
+
+fn render_nodes<'a>(
+    nodes: &'a [Node],
+    root: &'a Value,
+    scopes: &mut Vec<Scope<'a>>,
+    size_format: SizeFormat,
+    out: &mut String,
+) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Value { path, filter } => {
+                let value = lookup(path, root, scopes);
+                match filter {
+                    Filter::None => match value.as_ref() {
+                        Value::Null => {}
+                        Value::String(text) => out.push_str(text),
+                        other => out.push_str(&other.to_string()),
+                    },
+                    Filter::Size => {
+                        let bytes = value.as_u64().with_context(|| {
+                            format!("`{}` is not a number of bytes", path.join("."))
+                        })?;
+                        out.push_str(&format_size(bytes, size_format));
+                    }
+                    Filter::Json => out.push_str(&value.to_string()),
+                }
+            }
+            Node::For { name, path, body } => {
+                let items: &'a [Value] = match lookup(path, root, scopes) {
+                    Cow::Borrowed(Value::Array(items)) => items,
+                    Cow::Borrowed(Value::Null) => &[],
+                    _ => bail!("`{}` is not an array", path.join(".")),
+                };
+                for (index, item) in items.iter().enumerate() {
+                    scopes.push(Scope {
+                        name,
+                        item,
+                        index,
+                        last: index + 1 == items.len(),
+                    });
+                    let rendered = render_nodes(body, root, scopes, size_format, out);
+                    scopes.pop();
+                    rendered?;
+                }
+            }
+            Node::If {
+                path,
+                then,
+                otherwise,
+            } => {
+                let branch = if is_truthy(&lookup(path, root, scopes)) {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(branch, root, scopes, size_format, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Follows `path` from the innermost loop item of that name, or else from
+/// the report itself. Missing fields are `null`.
+fn lookup<'a>(path: &[String], root: &'a Value, scopes: &[Scope<'a>]) -> Cow<'a, Value> {
+    let (first, rest) = path.split_first().expect("paths have a segment");
+    let start = match first.as_str() {
+        "@index" => {
+            return Cow::Owned(
+                scopes
+                    .last()
+                    .map_or(Value::Null, |scope| scope.index.into()),
+            )
+        }
+        "@last" => return Cow::Owned(scopes.last().map_or(Value::Null, |scope| scope.last.into())),
+        name => match scopes.iter().rev().find(|scope| scope.name == name) {
+            Some(scope) => scope.item,
+            None => &root[name],
+        },
+    };
+    let mut value = start;
+    for segment in rest {
+        value = match (value, segment.parse::<usize>()) {
+            (Value::Array(items), Ok(index)) => items.get(index).unwrap_or(&Value::Null),
+            (value, _) => &value[segment.as_str()],
+        };
+    }
+    Cow::Borrowed(value)
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64() != Some(0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::PackageReport;
+
+    #[test]
+    fn test_report_template_renders_loops_and_conditions() {
+        let report = Report {
+            packages: vec![
+                PackageReport {
+                    name: "serde".to_string(),
+                    version: "1.0.0".to_string(),
+                    bytes: 2048,
+                    ..Default::default()
+                },
+                PackageReport {
+                    name: "itoa".to_string(),
+                    version: "1.0.11".to_string(),
+                    bytes: 100,
+                    ..Default::default()
+                },
+            ],
+            total_bytes: 2148,
+            package_count: 2,
+            sum_by: "direct",
+            ..Default::default()
+        };
+        let template = ReportTemplate::parse(
+            "\\{{{ for package in packages }}{package.name}={package.bytes}\
+             {{ if @last }}{{ else }},{{ endif }}{{ endfor }}\\} \
+             {packages.1.version} {total_bytes | size}\
+             {{ if comparison }} compared{{ endif }}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&report, SizeFormat::default()).unwrap(),
+            "{serde=2048,itoa=100} 1.0.11 2.10KB (2148 bytes)"
+        );
+    }
+
+    #[test]
+    fn test_report_template_rejects_unclosed_blocks() {
+        for template in [
+            "{{ for package in packages }}{package.name}",
+            "{{ if comparison }}",
+            "{{ endfor }}",
+            "{{ while x }}",
+            "{name | upper}",
+            "{name",
+        ] {
+            assert!(ReportTemplate::parse(template).is_err(), "{}", template);
+        }
+    }
+}
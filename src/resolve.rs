@@ -0,0 +1,729 @@
+use crate::cli::{DependencyKind, DepsizeArgs};
+use crate::tree;
+use anyhow::{bail, Context, Result};
+use cargo::core::compiler::{CompileKind, CompileTarget, RustcTargetData};
+use cargo::core::dependency::DepKind;
+use cargo::core::resolver::features::{CliFeatures, ForceAllTargets, HasDevUnits};
+use cargo::core::{Dependency, Package, PackageId, PackageIdSpec, Resolve, SourceId, Workspace};
+use cargo::ops::WorkspaceResolve;
+use cargo::GlobalContext;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Resolves the dependency graph of the workspace with every feature enabled
+/// and for every target platform, or only `--target` with `--strict-target`.
+///
+/// If that fails, resolution is retried with default features for the host
+/// platform only, so that a single optional or platform-specific dependency
+/// that can't be downloaded or loaded doesn't prevent sizing everything else.
+/// The packages left out this way are reported on stderr.
+pub fn resolve_dependencies<'gctx>(
+    workspace: &Workspace<'gctx>,
+    args: &DepsizeArgs,
+) -> Result<WorkspaceResolve<'gctx>> {
+    // Read before resolving, which may rewrite it
+    let lockfile = cargo::ops::load_pkg_lockfile(workspace)?;
+
+    let force_all_targets = if args.strict_target {
+        ForceAllTargets::No
+    } else {
+        ForceAllTargets::Yes
+    };
+    let workspace_resolve = match resolve_with(
+        workspace,
+        args,
+        CliFeatures::new_all(true),
+        force_all_targets,
+    ) {
+        Ok(workspace_resolve) => workspace_resolve,
+        Err(err) => resolve_fallback(workspace, args, err)?,
+    };
+    if args.strict_target {
+        report_platform_gated(workspace, args, &workspace_resolve)?;
+    }
+
+    if let (Some(lockfile), Some(resolve)) = (&lockfile, &workspace_resolve.workspace_resolve) {
+        let locked: HashSet<PackageId> = lockfile.iter().collect();
+        let resolved: HashSet<PackageId> = resolve.iter().collect();
+        if locked != resolved {
+            eprintln!(
+                "warning: Resolution differs from Cargo.lock; reported sizes may not match \
+                 your committed dependencies. Use --locked to pin."
+            );
+        }
+    }
+
+    Ok(workspace_resolve)
+}
+
+/// Retries a failed resolve with only the default features, for the host
+/// platform, returning `err` if that fails too.
+///
+/// Cargo can't leave out just the dependency that failed, so this drops
+/// every dependency that only non-default features or other platforms
+/// enable, and names each of them.
+fn resolve_fallback<'gctx>(
+    workspace: &Workspace<'gctx>,
+    args: &DepsizeArgs,
+    err: anyhow::Error,
+) -> Result<WorkspaceResolve<'gctx>> {
+    let workspace_resolve = match resolve_with(
+        workspace,
+        args,
+        CliFeatures::new_all(false),
+        ForceAllTargets::No,
+    ) {
+        Ok(workspace_resolve) => workspace_resolve,
+        // The first error is the more useful one, it's about the full graph
+        Err(_) => return Err(err).with_context(|| resolve_error_context(args)),
+    };
+
+    eprintln!("warning: {:#}", err);
+    eprintln!(
+        "warning: resolved again with default features for the host platform only, \
+         optional dependencies behind other features and other platforms' dependencies \
+         are not measured"
+    );
+
+    let skipped = skipped_dependencies(&workspace_resolve);
+    if !skipped.is_empty() {
+        eprintln!(
+            "note: skipped {} packages that need non-default features or another platform: {}",
+            skipped.len(),
+            skipped.into_iter().collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    Ok(workspace_resolve)
+}
+
+fn resolve_with<'gctx>(
+    workspace: &Workspace<'gctx>,
+    args: &DepsizeArgs,
+    cli_features: CliFeatures,
+    force_all_targets: ForceAllTargets,
+) -> Result<WorkspaceResolve<'gctx>> {
+    let requested_kinds = requested_kinds(args)?;
+    let mut target_data = RustcTargetData::new(workspace, &requested_kinds)?;
+    // Only "all features" may be resolved without naming the packages
+    let specs: Vec<PackageIdSpec> = if cli_features.all_features {
+        Vec::new()
+    } else {
+        workspace
+            .members()
+            .map(|member| member.package_id().to_spec())
+            .collect()
+    };
+    cargo::ops::resolve_ws_with_opts(
+        workspace,
+        &mut target_data,
+        &requested_kinds,
+        &cli_features,
+        &specs,
+        // Dev-only packages aren't even downloaded with `--exclude-kind dev`
+        if args.exclude_kind.contains(&DependencyKind::Dev) {
+            HasDevUnits::No
+        } else {
+            HasDevUnits::Yes
+        },
+        force_all_targets,
+        false,
+    )
+}
+
+/// The `--target` platform, or nothing for the host.
+fn requested_kinds(args: &DepsizeArgs) -> Result<Vec<CompileKind>> {
+    args.target
+        .iter()
+        .map(|triple| Ok(CompileKind::Target(CompileTarget::new(triple)?)))
+        .collect()
+}
+
+/// The dependencies declared by the workspace members whose `cfg` doesn't
+/// match the `--target` platform, by member name, along with its triple.
+pub fn platform_gated(
+    workspace: &Workspace<'_>,
+    args: &DepsizeArgs,
+) -> Result<(String, Vec<(String, Dependency)>)> {
+    let requested_kinds = requested_kinds(args)?;
+    let target_data = RustcTargetData::new(workspace, &requested_kinds)?;
+    let target = requested_kinds
+        .first()
+        .copied()
+        .unwrap_or(CompileKind::Host);
+
+    let mut gated = Vec::new();
+    for member in workspace.members() {
+        for dep in member.dependencies() {
+            // Build dependencies run on the host, whatever the target
+            let kind = match dep.kind() {
+                DepKind::Build => CompileKind::Host,
+                DepKind::Normal | DepKind::Development => target,
+            };
+            if !target_data.dep_platform_activated(dep, kind) {
+                gated.push((member.name().to_string(), dep.clone()));
+            }
+        }
+    }
+    Ok((target_data.short_name(&target).to_string(), gated))
+}
+
+/// Prints the dependencies `--strict-target` leaves out, with the `cfg`
+/// they're gated on, then every package that isn't measured because of them.
+fn report_platform_gated(
+    workspace: &Workspace<'_>,
+    args: &DepsizeArgs,
+    resolve: &WorkspaceResolve<'_>,
+) -> Result<()> {
+    let (triple, gated) = platform_gated(workspace, args)?;
+    let gated: BTreeSet<String> = gated
+        .iter()
+        .filter_map(|(member, dep)| {
+            let platform = dep.platform()?;
+            Some(format!(
+                "{} -> {} ({})",
+                member,
+                dep.package_name(),
+                platform
+            ))
+        })
+        .collect();
+    if gated.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!("note: dependencies left out for {}:", triple);
+    for line in &gated {
+        eprintln!("  {}", line);
+    }
+    let skipped = skipped_dependencies(resolve);
+    if !skipped.is_empty() {
+        eprintln!(
+            "note: {} packages aren't measured for {}: {}",
+            skipped.len(),
+            triple,
+            skipped.into_iter().collect::<Vec<_>>().join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn resolve_error_context(args: &DepsizeArgs) -> String {
+    match args.cache_only_mode() {
+        // Cargo's own error names the crate, but not why it had to go looking for it
+        Some(mode) => format!(
+            "failed to resolve dependencies using only the local cache ({mode}); \
+             the crates named below are missing from the cache, run `cargo fetch` first"
+        ),
+        None => "failed to resolve dependencies".to_string(),
+    }
+}
+
+/// Names of the packages in the full lockfile graph that were left out of
+/// the narrowed resolve and so never downloaded.
+fn skipped_dependencies(resolve: &WorkspaceResolve<'_>) -> BTreeSet<String> {
+    let Some(full_resolve) = &resolve.workspace_resolve else {
+        return BTreeSet::new();
+    };
+    let downloaded: BTreeSet<PackageId> = resolve
+        .pkg_set
+        .packages()
+        .map(|package| package.package_id())
+        .collect();
+
+    full_resolve
+        .iter()
+        .filter(|package_id| !downloaded.contains(package_id))
+        .map(|package_id| format!("{} v{}", package_id.name(), package_id.version()))
+        .collect()
+}
+
+/// Packages that were swapped in by a `[patch]` section, e.g. a crates.io
+/// dependency redirected to a local checkout or a git fork.
+pub fn patched_packages(
+    workspace: &Workspace<'_>,
+    resolve: &WorkspaceResolve<'_>,
+) -> Result<HashSet<PackageId>> {
+    let patches = workspace.root_patch()?;
+    let patched = resolve
+        .pkg_set
+        .packages()
+        .map(|package| package.package_id())
+        .filter(|package_id| {
+            patches.values().flatten().any(|patch| {
+                patch.package_name() == package_id.name()
+                    && patch.source_id().canonical_url() == package_id.source_id().canonical_url()
+            })
+        })
+        .collect();
+    Ok(patched)
+}
+
+/// Cuts the build-only edges of every package named `crate_name` out of
+/// `graph` and returns the packages the workspace `members` no longer reach.
+///
+/// Packages those build dependencies share with the rest of the graph, such
+/// as a normal dependency of another crate, stay reachable and are kept.
+/// Fails if no resolved package has that name.
+pub fn cut_build_dependencies(
+    graph: &mut HashMap<PackageId, BTreeSet<PackageId>>,
+    resolve: &Resolve,
+    members: &[PackageId],
+    crate_name: &str,
+) -> Result<HashSet<PackageId>> {
+    let owners: Vec<PackageId> = resolve
+        .iter()
+        .filter(|package_id| package_id.name() == crate_name)
+        .collect();
+    if owners.is_empty() {
+        bail!("`{}` is not a dependency of this workspace", crate_name);
+    }
+
+    let reached_from_members = |graph: &HashMap<PackageId, BTreeSet<PackageId>>| {
+        members
+            .iter()
+            .flat_map(|&member| tree::reachable(graph, member))
+            .collect::<HashSet<PackageId>>()
+    };
+    let before = reached_from_members(graph);
+    for owner in owners {
+        // An edge that is also a normal or dev dependency stays
+        let build_only = resolve
+            .deps(owner)
+            .filter(|(_, deps)| deps.iter().all(|dep| dep.kind() == DepKind::Build));
+        for (dep_id, _) in build_only {
+            if let Some(deps) = graph.get_mut(&owner) {
+                deps.remove(&dep_id);
+            }
+        }
+    }
+    let after = reached_from_members(graph);
+    Ok(before.difference(&after).copied().collect())
+}
+
+/// The packages a direct dependency name of `root` stands for: the latest
+/// resolved version of that name from each source `root` depends on it
+/// from. Without a matching edge in the resolve, the latest version of the
+/// name from anywhere.
+///
+/// Going by source keeps a workspace member and a published crate of the
+/// same name apart, instead of picking whichever has the higher version.
+pub fn packages_named(
+    resolve: &WorkspaceResolve<'_>,
+    root: PackageId,
+    name: &str,
+) -> Vec<PackageId> {
+    let sources: BTreeSet<SourceId> = resolve
+        .targeted_resolve
+        .deps(root)
+        .map(|(dep_id, _)| dep_id)
+        .filter(|dep_id| dep_id.name() == name)
+        .map(|dep_id| dep_id.source_id())
+        .collect();
+    let named = resolve
+        .pkg_set
+        .package_ids()
+        .filter(|package_id| package_id.name() == name);
+    if sources.is_empty() {
+        return named
+            .max_by_key(|package_id| package_id.version().clone())
+            .into_iter()
+            .collect();
+    }
+
+    let mut latest: HashMap<SourceId, PackageId> = HashMap::new();
+    for package_id in named.filter(|package_id| sources.contains(&package_id.source_id())) {
+        let entry = latest.entry(package_id.source_id()).or_insert(package_id);
+        if package_id.version() > entry.version() {
+            *entry = package_id;
+        }
+    }
+    latest.into_values().collect()
+}
+
+/// Workspace members that share their name with a resolved package from
+/// another source, as `(member, other)` pairs.
+pub fn member_name_collisions(
+    workspace: &Workspace<'_>,
+    resolve: &WorkspaceResolve<'_>,
+) -> Vec<(PackageId, PackageId)> {
+    let members: HashSet<PackageId> = workspace
+        .members()
+        .map(|member| member.package_id())
+        .collect();
+    let mut collisions: Vec<(PackageId, PackageId)> = resolve
+        .pkg_set
+        .package_ids()
+        .filter(|package_id| !members.contains(package_id))
+        .flat_map(|other| {
+            members
+                .iter()
+                .filter(move |member| member.name() == other.name())
+                .map(move |&member| (member, other))
+        })
+        .collect();
+    collisions.sort_unstable();
+    collisions
+}
+
+/// The source of the registry named `name` in `[registries]`, or crates.io
+/// for `crates-io`.
+pub fn registry_source(gctx: &GlobalContext, name: &str) -> Result<SourceId> {
+    SourceId::alt_registry(gctx, name).with_context(|| {
+        format!(
+            "registry `{}` is not configured, add it under [registries] in .cargo/config.toml",
+            name
+        )
+    })
+}
+
+/// The listed direct dependencies of `package` by name, with every kind of
+/// dependency they are, leaving out the `excluded` declarations.
+///
+/// Only normal dependencies, unless `--dedupe-kinds` or `--exclude-kind` lists
+/// dev and build dependencies too. Those inherited with `workspace = true` are
+/// already filled in from `[workspace.dependencies]` when the manifest loads.
+pub fn direct_dependencies(
+    package: &Package,
+    args: &DepsizeArgs,
+    excluded: &HashSet<Dependency>,
+) -> HashMap<String, BTreeSet<DepKind>> {
+    let mut dep_kinds: HashMap<String, BTreeSet<DepKind>> = HashMap::new();
+    for dep in package.dependencies() {
+        if args.lists_kind(dep.kind()) && !excluded.contains(dep) {
+            dep_kinds
+                .entry(dep.package_name().to_string())
+                .or_default()
+                .insert(dep.kind());
+        }
+    }
+    dep_kinds
+}
+
+/// The `--config` overrides for `--registry`: crates.io is replaced with the
+/// registry, as [source replacement] does for a mirror, so that packages
+/// keep their crates.io ids but are downloaded and unpacked from it.
+///
+/// [source replacement]: https://doc.rust-lang.org/cargo/reference/source-replacement.html
+pub fn registry_overrides(registry: Option<&str>) -> Vec<String> {
+    match registry {
+        // Replacing crates.io with itself would be a cycle
+        None | Some("crates-io") => Vec::new(),
+        Some(name) => vec![format!(
+            "source.crates-io.replace-with={}",
+            toml::Value::String(name.to_string())
+        )],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CargoCli;
+    use crate::walk;
+    use cargo::core::Shell;
+    use clap::Parser;
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn test_direct_dependencies_include_workspace_inherited_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\"]\nresolver = \"2\"\n\n\
+             [workspace.dependencies]\nitoa = \"1\"\ncfg-if = \"1\"\nhex = \"0.4\"\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.path().join("app/src")).unwrap();
+        std::fs::write(dir.path().join("app/src/lib.rs"), "").unwrap();
+        std::fs::write(
+            dir.path().join("app/Cargo.toml"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+             [dependencies]\nitoa.workspace = true\nhex = { workspace = true, optional = true }\n\n\
+             [build-dependencies]\ncfg-if = { workspace = true }\n\n\
+             [dev-dependencies]\nhex.workspace = true\n",
+        )
+        .unwrap();
+        let gctx = GlobalContext::new(
+            Shell::new(),
+            dir.path().to_path_buf(),
+            dir.path().join("cargo-home"),
+        );
+        let workspace = Workspace::new(&dir.path().join("app/Cargo.toml"), &gctx).unwrap();
+        let app = workspace.current().unwrap();
+
+        let CargoCli::Depsize(args) = CargoCli::parse_from(["cargo", "depsize"]);
+        let normal = direct_dependencies(app, &args, &HashSet::new());
+        assert_eq!(
+            normal.keys().map(String::as_str).collect::<BTreeSet<_>>(),
+            BTreeSet::from(["hex", "itoa"])
+        );
+
+        let CargoCli::Depsize(args) = CargoCli::parse_from(["cargo", "depsize", "--dedupe-kinds"]);
+        let all = direct_dependencies(app, &args, &HashSet::new());
+        assert_eq!(all["itoa"], BTreeSet::from([DepKind::Normal]));
+        assert_eq!(all["cfg-if"], BTreeSet::from([DepKind::Build]));
+        assert_eq!(
+            all["hex"],
+            BTreeSet::from([DepKind::Normal, DepKind::Development])
+        );
+    }
+
+    #[test]
+    fn test_member_and_outside_package_with_the_same_name_stay_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write(
+            "ws/Cargo.toml",
+            "[workspace]\nmembers = [\"app\", \"util\"]\nresolver = \"2\"\n",
+        );
+        write(
+            "ws/app/Cargo.toml",
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+             [dependencies]\nutil = { path = \"../../vendor/util\" }\n",
+        );
+        write("ws/app/src/lib.rs", "");
+        // The member has the higher version, so picking by name alone
+        // would measure it instead of the dependency
+        write(
+            "ws/util/Cargo.toml",
+            "[package]\nname = \"util\"\nversion = \"2.0.0\"\nedition = \"2021\"\n",
+        );
+        write("ws/util/src/lib.rs", "");
+        write(
+            "vendor/util/Cargo.toml",
+            "[package]\nname = \"util\"\nversion = \"1.0.0\"\nedition = \"2021\"\n",
+        );
+        write("vendor/util/src/lib.rs", "");
+
+        let gctx = GlobalContext::new(
+            Shell::new(),
+            dir.path().to_path_buf(),
+            dir.path().join("cargo-home"),
+        );
+        let workspace = Workspace::new(&dir.path().join("ws/app/Cargo.toml"), &gctx).unwrap();
+        let CargoCli::Depsize(args) = CargoCli::parse_from(["cargo", "depsize", "--offline"]);
+        let resolve = resolve_dependencies(&workspace, &args).unwrap();
+        let app = workspace.current().unwrap().package_id();
+
+        let util = packages_named(&resolve, app, "util");
+        assert_eq!(util.len(), 1);
+        assert_eq!(util[0].version().to_string(), "1.0.0");
+
+        let collisions = member_name_collisions(&workspace, &resolve);
+        assert_eq!(collisions.len(), 1);
+        let (member, other) = collisions[0];
+        assert_eq!(member.version().to_string(), "2.0.0");
+        assert_eq!(other, util[0]);
+    }
+
+    #[test]
+    fn test_cut_build_dependencies_keeps_shared_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        let manifest = |name: &str, sections: &str| {
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n{}",
+                name, sections
+            )
+        };
+        write(
+            "app/Cargo.toml",
+            &manifest(
+                "app",
+                "[dependencies]\na = { path = \"../a\" }\nb = { path = \"../b\" }\n\
+                 c = { path = \"../c\" }\n",
+            ),
+        );
+        // c is also a normal dependency of app, only d is b's alone
+        write(
+            "b/Cargo.toml",
+            &manifest(
+                "b",
+                "[build-dependencies]\nc = { path = \"../c\" }\nd = { path = \"../d\" }\n",
+            ),
+        );
+        for name in ["a", "c", "d"] {
+            write(&format!("{}/Cargo.toml", name), &manifest(name, ""));
+        }
+        for name in ["app", "a", "b", "c", "d"] {
+            write(&format!("{}/src/lib.rs", name), "");
+        }
+
+        let gctx = GlobalContext::new(
+            Shell::new(),
+            dir.path().to_path_buf(),
+            dir.path().join("cargo-home"),
+        );
+        let workspace = Workspace::new(&dir.path().join("app/Cargo.toml"), &gctx).unwrap();
+        let CargoCli::Depsize(args) = CargoCli::parse_from(["cargo", "depsize", "--offline"]);
+        let resolve = resolve_dependencies(&workspace, &args).unwrap();
+        let resolve = &resolve.targeted_resolve;
+        let app = workspace.current().unwrap().package_id();
+        let id = |name: &str| resolve.iter().find(|id| id.name() == name).unwrap();
+
+        let mut graph = tree::dependency_graph(resolve, &args);
+        let cut = cut_build_dependencies(&mut graph, resolve, &[app], "b").unwrap();
+        assert_eq!(cut, HashSet::from([id("d")]));
+        assert!(graph[&id("b")].is_empty());
+        assert!(graph[&app].contains(&id("c")));
+        assert!(cut_build_dependencies(&mut graph, resolve, &[app], "missing").is_err());
+    }
+
+    #[test]
+    fn test_registry_source_finds_alternative_registries() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".cargo")).unwrap();
+        std::fs::write(
+            dir.path().join(".cargo/config.toml"),
+            "[registries.internal]\nindex = \"sparse+https://crates.example.com/index/\"\n",
+        )
+        .unwrap();
+        let gctx = GlobalContext::new(
+            Shell::new(),
+            dir.path().to_path_buf(),
+            dir.path().join("cargo-home"),
+        );
+
+        let internal = registry_source(&gctx, "internal").unwrap();
+        assert_eq!(internal.alt_registry_key(), Some("internal"));
+        assert!(registry_source(&gctx, "missing").is_err());
+        assert!(registry_source(&gctx, "crates-io").unwrap().is_crates_io());
+        assert!(registry_overrides(Some("crates-io")).is_empty());
+    }
+
+    #[test]
+    fn test_registry_resolves_crates_io_dependencies_from_its_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &[u8]| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        let manifest = "[package]\nname = \"tool\"\nversion = \"1.0.0\"\nedition = \"2021\"\n";
+        let lib = "pub fn tool() {}\n";
+
+        // A git index with a single crate, downloaded from a file:// URL
+        let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for (path, contents) in [("Cargo.toml", manifest), ("src/lib.rs", lib)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            archive
+                .append_data(
+                    &mut header,
+                    format!("tool-1.0.0/{}", path),
+                    contents.as_bytes(),
+                )
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap().finish().unwrap();
+        write("dl/tool-1.0.0.crate", &archive);
+        let checksum = cargo_util::Sha256::new().update(&archive).finish_hex();
+        write(
+            "index/config.json",
+            format!(
+                r#"{{"dl": "file://{}/dl/{{crate}}-{{version}}.crate"}}"#,
+                dir.path().display()
+            )
+            .as_bytes(),
+        );
+        write(
+            "index/to/ol/tool",
+            format!(
+                r#"{{"name": "tool", "vers": "1.0.0", "deps": [], "cksum": "{}", "features": {{}}, "yanked": false}}"#,
+                checksum
+            )
+            .as_bytes(),
+        );
+        for git in [
+            &["init", "--quiet"][..],
+            &["add", "."],
+            &[
+                "-c",
+                "user.name=depsize",
+                "-c",
+                "user.email=depsize@example.com",
+                "commit",
+                "--quiet",
+                "-m",
+                "index",
+            ],
+        ] {
+            let status = std::process::Command::new("git")
+                .args(git)
+                .current_dir(dir.path().join("index"))
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+        write(
+            ".cargo/config.toml",
+            format!(
+                "[registries.internal]\nindex = \"file://{}/index\"\n",
+                dir.path().display()
+            )
+            .as_bytes(),
+        );
+        write(
+            "app/Cargo.toml",
+            b"[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+              [dependencies]\ntool = \"1\"\n",
+        );
+        write("app/src/lib.rs", b"");
+
+        let mut gctx = GlobalContext::new(
+            Shell::new(),
+            dir.path().to_path_buf(),
+            dir.path().join("cargo-home"),
+        );
+        gctx.configure(
+            0,
+            false,
+            None,
+            false,
+            false,
+            false,
+            &None,
+            &[],
+            &registry_overrides(Some("internal")),
+        )
+        .unwrap();
+        let workspace = Workspace::new(&dir.path().join("app/Cargo.toml"), &gctx).unwrap();
+        let CargoCli::Depsize(args) =
+            CargoCli::parse_from(["cargo", "depsize", "--registry", "internal", "--canonical"]);
+        let resolve = resolve_dependencies(&workspace, &args).unwrap();
+        let tool = resolve
+            .pkg_set
+            .packages()
+            .find(|package| package.name() == "tool")
+            .unwrap();
+
+        // Still a crates.io package, but unpacked from the internal registry
+        assert!(tool.package_id().source_id().is_crates_io());
+        let unpacked_in = tool.root().parent().unwrap();
+        assert_eq!(
+            unpacked_in.parent().unwrap(),
+            dir.path().join("cargo-home/registry/src")
+        );
+        assert!(!unpacked_in.ends_with("index.crates.io-6f17d22bba15001f"));
+        let size = walk::calculate_package_size(
+            tool.root(),
+            walk::WalkOptions::from_args(&args),
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(size.bytes, (manifest.len() + lib.len()) as u64);
+    }
+}
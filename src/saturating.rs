@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static SATURATED: AtomicBool = AtomicBool::new(false);
+
+/// Adds `bytes` to `total`, stopping at `u64::MAX` instead of overflowing.
+///
+/// The first time a size saturates a warning is printed, since every total
+/// including it is then only a lower bound.
+pub fn add(total: &mut u64, bytes: u64) {
+    match total.checked_add(bytes) {
+        Some(sum) => *total = sum,
+        None => {
+            *total = u64::MAX;
+            if !SATURATED.swap(true, Ordering::Relaxed) {
+                eprintln!(
+                    "warning: sizes add up to more than {} bytes, totals are capped there \
+                     and are only a lower bound",
+                    u64::MAX
+                );
+            }
+        }
+    }
+}
+
+/// Sums `sizes` like [`add`].
+pub fn sum(sizes: impl IntoIterator<Item = u64>) -> u64 {
+    let mut total = 0;
+    for bytes in sizes {
+        add(&mut total, bytes);
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sum_saturates() {
+        assert_eq!(sum([1, 2, 3]), 6);
+        assert_eq!(sum([u64::MAX - 1, 2, 3]), u64::MAX);
+    }
+}
@@ -0,0 +1,228 @@
+/// The JSON Schema of the `--json` [`Report`](crate::report::Report), printed
+/// by `--report-schema`.
+///
+/// It's written by hand and kept next to the structs it describes; the test
+/// below fails when a serialized field is missing from it or doesn't match.
+pub const REPORT_SCHEMA: &str = include_str!("report.schema.json");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::{ChangeStatus, Comparison, FileChange, FileDiff, PackageChange};
+    use crate::group::{Group, Grouping};
+    use crate::report::{
+        DepsOver, Estimate, GroupTotal, HistogramBucket, LargeFile, PackageReport, Percentiles,
+        Report,
+    };
+    use crate::walk::DirBreakdown;
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+    use std::process::Command;
+
+    /// Checks `value` against the subset of JSON Schema `report.schema.json`
+    /// uses, returning the path of the first mismatch.
+    fn check(root: &Value, schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return check(root, &root["$defs"][name], value, path);
+        }
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                return Err(format!("{}: {} isn't one of {:?}", path, value, allowed));
+            }
+        }
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(name) => vec![name],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let type_matches = |name: &str| match name {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_u64() || value.is_i64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => false,
+        };
+        if !types.is_empty() && !types.iter().any(|name| type_matches(name)) {
+            return Err(format!("{}: {} isn't of type {:?}", path, value, types));
+        }
+
+        match value {
+            Value::Object(fields) => {
+                for required in schema["required"].as_array().into_iter().flatten() {
+                    let required = required.as_str().unwrap_or_default();
+                    if !fields.contains_key(required) {
+                        return Err(format!("{}: missing {}", path, required));
+                    }
+                }
+                for (key, field) in fields {
+                    let field_path = format!("{}.{}", path, key);
+                    match (&schema["properties"][key], &schema["additionalProperties"]) {
+                        (Value::Null, Value::Bool(false)) => {
+                            return Err(format!("{}: not in the schema", field_path))
+                        }
+                        (Value::Null, Value::Object(_)) => {
+                            check(root, &schema["additionalProperties"], field, &field_path)?
+                        }
+                        (Value::Null, _) => {}
+                        (property, _) => check(root, property, field, &field_path)?,
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    check(root, &schema["items"], item, &format!("{}[{}]", path, i))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Validates `value` against `schema` with the `jsonschema` Python package,
+    /// which implements the whole specification, checking the schema itself
+    /// too. Returns `None` when it isn't installed.
+    fn check_with_jsonschema(schema: &str, value: &Value) -> Option<Result<(), String>> {
+        const VALIDATE: &str = "import json, sys, jsonschema\n\
+            schema = json.loads(sys.argv[1])\n\
+            jsonschema.validators.validator_for(schema).check_schema(schema)\n\
+            jsonschema.validate(json.loads(sys.argv[2]), schema)\n";
+        let installed = Command::new("python3")
+            .args(["-c", "import jsonschema"])
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !installed {
+            return None;
+        }
+        let output = Command::new("python3")
+            .args(["-c", VALIDATE, schema, &value.to_string()])
+            .output()
+            .unwrap();
+        Some(if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).into_owned())
+        })
+    }
+
+    /// A package with every optional field filled in.
+    fn full_package() -> PackageReport {
+        PackageReport {
+            name: "serde".to_string(),
+            version: "1.0.0".to_string(),
+            bytes: 300,
+            files: 3,
+            source: "crates.io".to_string(),
+            url: Some("https://crates.io/crates/serde".to_string()),
+            path: Some("/registry/serde-1.0.0".into()),
+            dirs: Some(DirBreakdown::default()),
+            artifact_bytes: Some(1000),
+            source_percent_of_artifacts: Some(30.0),
+            compile_seconds: Some(1.5),
+            metadata_bytes: Some(20),
+            install_seconds: Some(0.25),
+            archive_bytes: Some(100),
+            kinds: Some(vec!["normal", "dev", "build"]),
+            file_sizes: Some(BTreeMap::from([("src/lib.rs".to_string(), 200)])),
+            patched: true,
+            yanked: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_schema_describes_every_report_field() {
+        let group = Group {
+            key: "MIT".to_string(),
+            count: 1,
+            bytes: 300,
+            packages: vec!["serde (v1.0.0)".to_string()],
+        };
+        let report = Report {
+            packages: vec![full_package()],
+            proc_macros: Some(GroupTotal::default()),
+            collapsed: Some(GroupTotal::default()),
+            self_package: Some(full_package()),
+            denied: vec![full_package()],
+            histogram: Some(vec![HistogramBucket {
+                label: ">= 1MB",
+                max_bytes: None,
+                count: 1,
+                bytes: 300,
+            }]),
+            grouping: Some(Grouping {
+                field: "license",
+                groups: vec![group.clone()],
+            }),
+            top_files: Some(vec![LargeFile {
+                package: "serde v1.0.0".to_string(),
+                path: "src/lib.rs".into(),
+                bytes: 200,
+            }]),
+            duplicates: Some(vec![group]),
+            comparison: Some(Comparison {
+                old_total_bytes: 0,
+                new_total_bytes: 300,
+                changes: vec![PackageChange {
+                    name: "serde".to_string(),
+                    status: ChangeStatus::Added,
+                    old_version: None,
+                    new_version: Some("1.0.0".to_string()),
+                    old_bytes: None,
+                    new_bytes: Some(300),
+                }],
+            }),
+            file_diff: Some(FileDiff {
+                name: "serde".to_string(),
+                old_version: "0.9.0".to_string(),
+                new_version: "1.0.0".to_string(),
+                old_bytes: 100,
+                new_bytes: 300,
+                changes: vec![FileChange {
+                    path: "src/lib.rs".to_string(),
+                    status: ChangeStatus::Changed,
+                    old_bytes: Some(100),
+                    new_bytes: Some(200),
+                }],
+            }),
+            deps_over: Some(DepsOver {
+                threshold_bytes: 100,
+                count: 1,
+            }),
+            percentiles: Some(Percentiles::default()),
+            metadata_overhead: Some(GroupTotal::default()),
+            install_seconds: Some(0.25),
+            estimate: Some(Estimate {
+                sample_rate: 0.1,
+                margin_bytes: Some(5),
+            }),
+            interrupted: true,
+            package_count: 1,
+            total_bytes: 300,
+            sum_by: "direct",
+        };
+
+        let empty = Report {
+            sum_by: "unique",
+            ..Default::default()
+        };
+        let schema: Value = serde_json::from_str(REPORT_SCHEMA).unwrap();
+        let mut validated = true;
+        for report in [report, empty] {
+            let report = serde_json::to_value(&report).unwrap();
+            check(&schema, &schema, &report, "$").unwrap();
+            match check_with_jsonschema(REPORT_SCHEMA, &report) {
+                Some(result) => result.unwrap(),
+                None => validated = false,
+            }
+        }
+        if !validated {
+            eprintln!(
+                "note: the jsonschema Python package isn't installed, only the subset is checked"
+            );
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::cli::DepsizeArgs;
+use crate::walk::{measure_packages_with, Measured, PackageSize, WalkOptions};
+use anyhow::{bail, Context, Result};
+use cargo::core::PackageId;
+use cargo::GlobalContext;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Known sizes of published packages, keyed by `name@version`, loaded with
+/// `--size-index`.
+#[derive(Debug, Default)]
+pub struct SizeIndex {
+    sizes: HashMap<String, u64>,
+}
+
+/// The formats `--size-index` accepts.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IndexFile {
+    /// A `cargo depsize --json` report.
+    Report {
+        packages: Vec<IndexedPackage>,
+        /// Missing from reports older than `--sum-by`.
+        #[serde(default)]
+        sum_by: Option<String>,
+        #[serde(default)]
+        estimate: Option<IgnoredAny>,
+    },
+    /// `{"name@version": bytes, ...}`
+    Map(HashMap<String, u64>),
+}
+
+/// A package of a saved report, with what tells how it was measured.
+#[derive(Deserialize)]
+struct IndexedPackage {
+    name: String,
+    version: String,
+    bytes: u64,
+    /// Only with `--disk-impact`, which adds the archive to `bytes`.
+    #[serde(default)]
+    archive_bytes: Option<u64>,
+}
+
+impl SizeIndex {
+    /// Reads the index from a file, or downloads it if `location` is an
+    /// `http://` or `https://` URL, which `--offline` and `--frozen` forbid.
+    pub fn load(gctx: &GlobalContext, location: &str) -> Result<Self> {
+        let contents = if location.starts_with("http://") || location.starts_with("https://") {
+            if !gctx.network_allowed() {
+                bail!(
+                    "can't download size index {} with --offline or --frozen, \
+                     save it to a file and pass its path instead",
+                    location
+                );
+            }
+            download(gctx, location)
+                .with_context(|| format!("failed to download size index {}", location))?
+        } else {
+            std::fs::read_to_string(location)
+                .with_context(|| format!("failed to read size index {}", location))?
+        };
+        Self::parse(&contents).with_context(|| format!("unusable size index {}", location))
+    }
+
+    /// Parses an index, rejecting reports whose sizes are something other
+    /// than each package's own sources.
+    fn parse(contents: &str) -> Result<Self> {
+        let index = serde_json::from_str(contents).context(
+            "neither a `cargo depsize --json` report nor a {\"name@version\": bytes} map",
+        )?;
+        let sizes = match index {
+            IndexFile::Report {
+                packages,
+                sum_by,
+                estimate,
+            } => {
+                let mode = if let Some(sum_by) = sum_by.filter(|sum_by| sum_by != "direct") {
+                    Some(format!("--sum-by {}", sum_by))
+                } else if estimate.is_some() {
+                    Some("--sample-rate".to_string())
+                } else if packages
+                    .iter()
+                    .any(|package| package.archive_bytes.is_some())
+                {
+                    Some("--disk-impact".to_string())
+                } else {
+                    None
+                };
+                if let Some(mode) = mode {
+                    bail!(
+                        "the report was saved with {}, its sizes aren't the packages' own sources",
+                        mode
+                    );
+                }
+                packages
+                    .into_iter()
+                    .map(|package| {
+                        (
+                            format!("{}@{}", package.name, package.version),
+                            package.bytes,
+                        )
+                    })
+                    .collect()
+            }
+            IndexFile::Map(sizes) => sizes,
+        };
+        Ok(SizeIndex { sizes })
+    }
+
+    /// The indexed size of a package, if it comes from a registry.
+    ///
+    /// Path and git packages can change without a version bump, so their
+    /// sizes are never taken from the index.
+    pub fn get(&self, package_id: PackageId) -> Option<u64> {
+        if !package_id.source_id().is_registry() {
+            return None;
+        }
+        self.sizes
+            .get(&format!("{}@{}", package_id.name(), package_id.version()))
+            .copied()
+    }
+}
+
+fn download(gctx: &GlobalContext, url: &str) -> Result<String> {
+    let mut handle = cargo::util::network::http::http_handle(gctx)?;
+    handle.url(url)?;
+    let mut body = Vec::new();
+    {
+        let mut transfer = handle.transfer();
+        transfer.write_function(|data| {
+            body.extend_from_slice(data);
+            Ok(data.len())
+        })?;
+        transfer.perform()?;
+    }
+    let status = handle.response_code()?;
+    if status != 200 {
+        bail!("the server responded with status {}", status);
+    }
+    Ok(String::from_utf8(body)?)
+}
+
+/// Like [`measure_packages`](crate::walk::measure_packages), but with `--size-index` the packages found in
+/// the index aren't walked. Their file counts and breakdowns are unknown and
+/// left at zero. `on_measured` is called with each package as soon as its
+/// size is known, the indexed ones first.
+pub async fn measure_packages_indexed(
+    gctx: &GlobalContext,
+    args: &DepsizeArgs,
+    packages: Vec<(PackageId, String, PathBuf)>,
+    mut on_measured: impl FnMut(PackageId, &PackageSize) -> Result<()>,
+) -> Result<Measured<PackageId>> {
+    let index = match &args.size_index {
+        Some(location) => SizeIndex::load(gctx, location)?,
+        None => SizeIndex::default(),
+    };
+
+    let mut indexed = HashMap::new();
+    let mut to_walk = Vec::new();
+    for (package_id, label, path) in packages {
+        match index.get(package_id) {
+            Some(bytes) => {
+                let size = PackageSize::with_bytes(bytes);
+                on_measured(package_id, &size)?;
+                indexed.insert(package_id, size);
+            }
+            None => to_walk.push((package_id, label, path)),
+        }
+    }
+    if args.size_index.is_some() {
+        eprintln!(
+            "note: took {} sizes from the index, measuring the other {}",
+            indexed.len(),
+            to_walk.len()
+        );
+    }
+
+    let mut measured = measure_packages_with(to_walk, WalkOptions::from_args(args), |id, size| {
+        on_measured(*id, size)
+    })
+    .await?;
+    measured.sizes.extend(indexed);
+    Ok(measured)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo::core::SourceId;
+
+    #[test]
+    fn test_size_index_reads_reports_and_maps() {
+        let report = r#"{"packages": [{"name": "serde", "version": "1.0.0", "bytes": 10}], "total_bytes": 10, "sum_by": "direct"}"#;
+        let map = r#"{"serde@1.0.0": 10}"#;
+        let registry =
+            SourceId::crates_io_maybe_sparse_http(&GlobalContext::default().unwrap()).unwrap();
+        let serde = PackageId::try_new("serde", "1.0.0", registry).unwrap();
+        let other = PackageId::try_new("serde", "1.0.1", registry).unwrap();
+
+        for contents in [report, map] {
+            let index = SizeIndex::parse(contents).unwrap();
+            assert_eq!(index.get(serde), Some(10));
+            assert_eq!(index.get(other), None);
+        }
+        assert!(SizeIndex::parse("[1, 2]").is_err());
+
+        for measured_otherwise in [
+            report.replace("direct", "transitive"),
+            report.replace(r#""bytes": 10}"#, r#""bytes": 10, "archive_bytes": 2}"#),
+            report.replace(
+                "}], ",
+                r#"}], "estimate": {"sample_rate": 0.5, "margin_bytes": 1}, "#,
+            ),
+        ] {
+            assert!(SizeIndex::parse(&measured_otherwise).is_err());
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::report::{format_size, PackageReport, SizeFormat};
+
+/// A `--format-template` string, parsed once at startup so that unknown
+/// placeholders are reported before any work is done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Placeholder {
+    Name,
+    Version,
+    Size,
+    Bytes,
+    Percent,
+}
+
+const PLACEHOLDERS: &[(&str, Placeholder)] = &[
+    ("name", Placeholder::Name),
+    ("version", Placeholder::Version),
+    ("size", Placeholder::Size),
+    ("bytes", Placeholder::Bytes),
+    ("percent", Placeholder::Percent),
+];
+
+impl Template {
+    /// Parses a template such as `{name}={bytes}`. Literal braces are written
+    /// as `{{` and `}}`.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed placeholder `{{{name}`")),
+                        }
+                    }
+                    let placeholder = PLACEHOLDERS
+                        .iter()
+                        .find(|(known, _)| *known == name)
+                        .map(|(_, placeholder)| *placeholder)
+                        .ok_or_else(|| {
+                            let known: Vec<String> = PLACEHOLDERS
+                                .iter()
+                                .map(|(known, _)| format!("{{{known}}}"))
+                                .collect();
+                            format!(
+                                "unknown placeholder `{{{name}}}`, expected one of {}",
+                                known.join(", ")
+                            )
+                        })?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Placeholder(placeholder));
+                }
+                '}' => return Err("unmatched `}`, write `}}` for a literal brace".to_string()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Template { parts })
+    }
+
+    /// Renders the template for one package; `percent` is its share of the total.
+    pub fn render(&self, package: &PackageReport, percent: f64, size_format: SizeFormat) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => line.push_str(literal),
+                Part::Placeholder(Placeholder::Name) => line.push_str(&package.name),
+                Part::Placeholder(Placeholder::Version) => line.push_str(package.display_version()),
+                Part::Placeholder(Placeholder::Size) => {
+                    line.push_str(&format_size(package.bytes, size_format))
+                }
+                Part::Placeholder(Placeholder::Bytes) => line.push_str(&package.bytes.to_string()),
+                Part::Placeholder(Placeholder::Percent) => {
+                    line.push_str(&format!("{:.2}", percent))
+                }
+            }
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_render() {
+        let template = Template::parse("{name}={bytes} {{{percent}%}}").unwrap();
+        let package = PackageReport {
+            name: "serde".to_string(),
+            bytes: 2048,
+            ..Default::default()
+        };
+        assert_eq!(
+            template.render(&package, 12.5, SizeFormat::default()),
+            "serde=2048 {12.50%}"
+        );
+    }
+
+    #[test]
+    fn test_template_rejects_unknown_placeholder() {
+        let err = Template::parse("{name} {colour}").unwrap_err();
+        assert!(err.contains("`{colour}`"), "{}", err);
+    }
+}
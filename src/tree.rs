@@ -0,0 +1,256 @@
+use crate::cli::{DepsizeArgs, TreeSort};
+use crate::report::format_size;
+use crate::resolve::resolve_dependencies;
+use crate::saturating;
+use crate::size_index::measure_packages_indexed;
+use crate::walk::{Measured, PackageSize};
+use anyhow::Result;
+use cargo::core::{PackageId, Resolve, Workspace};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Prints the dependency tree of every workspace member, like `cargo tree`,
+/// with the size of each package and of everything below it.
+pub async fn print_tree(workspace: &Workspace<'_>, args: &DepsizeArgs) -> Result<()> {
+    let workspace_resolve = resolve_dependencies(workspace, args)?;
+    let resolve = &workspace_resolve.targeted_resolve;
+
+    let dependencies = dependency_graph(resolve, args);
+
+    let packages = workspace_resolve
+        .pkg_set
+        .packages()
+        .map(|package| {
+            let package_id = package.package_id();
+            let label = format!("{} v{}", package_id.name(), package_id.version());
+            (package_id, label, package.root().to_path_buf())
+        })
+        .collect();
+    let Measured { sizes, interrupted } =
+        measure_packages_indexed(workspace.gctx(), args, packages, |_, _| Ok(())).await?;
+    if interrupted {
+        outln!("(interrupted, partial results)");
+    }
+
+    let tree = Tree {
+        subtree_bytes: subtree_bytes(&dependencies, &sizes),
+        dependencies: &dependencies,
+        sizes: &sizes,
+        args,
+    };
+    let members: BTreeSet<PackageId> = workspace
+        .members()
+        .map(|member| member.package_id())
+        .collect();
+    for (i, member) in tree.ordered(members.iter()).into_iter().enumerate() {
+        if i > 0 {
+            outln!();
+        }
+        print_branches(
+            member,
+            &|package_id| tree.children(package_id),
+            &|package_id, repeated| tree.label(package_id, repeated),
+        );
+    }
+
+    Ok(())
+}
+
+/// What each resolved package depends on, leaving out the edges
+/// `--exclude-kind` drops.
+pub fn dependency_graph(
+    resolve: &Resolve,
+    args: &DepsizeArgs,
+) -> HashMap<PackageId, BTreeSet<PackageId>> {
+    resolve
+        .iter()
+        .map(|package_id| {
+            let deps = resolve
+                .deps(package_id)
+                .filter(|(_, dependencies)| args.keeps_edge(dependencies))
+                .map(|(dep_id, _)| dep_id)
+                .collect();
+            (package_id, deps)
+        })
+        .collect()
+}
+
+/// `root` and everything it depends on, directly or not.
+pub fn reachable(
+    dependencies: &HashMap<PackageId, BTreeSet<PackageId>>,
+    root: PackageId,
+) -> HashSet<PackageId> {
+    let mut reached = HashSet::new();
+    let mut pending = vec![root];
+    while let Some(package_id) = pending.pop() {
+        if reached.insert(package_id) {
+            pending.extend(dependencies.get(&package_id).into_iter().flatten());
+        }
+    }
+    reached
+}
+
+/// The combined size of each package and everything it depends on,
+/// directly or not, counting packages reached along several paths once.
+fn subtree_bytes(
+    dependencies: &HashMap<PackageId, BTreeSet<PackageId>>,
+    sizes: &HashMap<PackageId, PackageSize>,
+) -> HashMap<PackageId, u64> {
+    dependencies
+        .keys()
+        .map(|&root| {
+            let reached = reachable(dependencies, root);
+            let bytes = saturating::sum(
+                reached
+                    .iter()
+                    .filter_map(|package_id| sizes.get(package_id))
+                    .map(|size| size.bytes),
+            );
+            (root, bytes)
+        })
+        .collect()
+}
+
+struct Tree<'a> {
+    dependencies: &'a HashMap<PackageId, BTreeSet<PackageId>>,
+    sizes: &'a HashMap<PackageId, PackageSize>,
+    subtree_bytes: HashMap<PackageId, u64>,
+    args: &'a DepsizeArgs,
+}
+
+impl Tree<'_> {
+    /// `packages` in `--tree-sort` order: the heaviest subtree first, or by
+    /// name.
+    fn ordered<'p>(&self, packages: impl Iterator<Item = &'p PackageId>) -> Vec<PackageId> {
+        let mut packages: Vec<PackageId> = packages.copied().collect();
+        match self.args.tree_sort {
+            // Ties stay in name order, `packages` comes sorted
+            TreeSort::Size => packages.sort_by_key(|package_id| {
+                std::cmp::Reverse(self.subtree_bytes.get(package_id).copied().unwrap_or(0))
+            }),
+            TreeSort::Name => packages.sort(),
+        }
+        packages
+    }
+
+    /// What goes below `package_id` in the tree, in `--tree-sort` order.
+    fn children(&self, package_id: PackageId) -> Vec<PackageId> {
+        self.ordered(self.dependencies.get(&package_id).into_iter().flatten())
+    }
+
+    /// The size of `package_id` and, unless it's `repeated`, of everything
+    /// below it.
+    fn label(&self, package_id: PackageId, repeated: bool) -> String {
+        let size = size_label(self.sizes, package_id, self.args);
+        let has_dependencies = self
+            .dependencies
+            .get(&package_id)
+            .is_some_and(|deps| !deps.is_empty());
+        if !has_dependencies || repeated {
+            return size;
+        }
+        format!(
+            "{} ({} with dependencies)",
+            size,
+            format_size(
+                self.subtree_bytes.get(&package_id).copied().unwrap_or(0),
+                self.args.size_format()
+            )
+        )
+    }
+}
+
+/// The measured size of `package_id`, formatted for the tree views.
+pub fn size_label(
+    sizes: &HashMap<PackageId, PackageSize>,
+    package_id: PackageId,
+    args: &DepsizeArgs,
+) -> String {
+    match sizes.get(&package_id) {
+        Some(size) => format_size(size.bytes, args.size_format()),
+        None => "not measured".to_string(),
+    }
+}
+
+/// Prints `root` and everything `children` puts below it, like `cargo tree`,
+/// with `label` after each package's name. `label` is told whether the
+/// package was already expanded, in which case it's marked `(*)` and its
+/// children aren't repeated.
+pub fn print_branches(
+    root: PackageId,
+    children: &dyn Fn(PackageId) -> Vec<PackageId>,
+    label: &dyn Fn(PackageId, bool) -> String,
+) {
+    print_branch(root, "", None, &mut HashSet::new(), children, label);
+}
+
+/// `last` is `None` for the top of the tree, otherwise whether this is the
+/// last entry at its level.
+fn print_branch(
+    package_id: PackageId,
+    prefix: &str,
+    last: Option<bool>,
+    seen: &mut HashSet<PackageId>,
+    children: &dyn Fn(PackageId) -> Vec<PackageId>,
+    label: &dyn Fn(PackageId, bool) -> String,
+) {
+    let branch = match last {
+        None => "",
+        Some(false) => "├── ",
+        Some(true) => "└── ",
+    };
+    let below = children(package_id);
+    let repeated = !seen.insert(package_id) && !below.is_empty();
+    outln!(
+        "{}{}{} v{} : {}{}",
+        prefix,
+        branch,
+        package_id.name(),
+        package_id.version(),
+        label(package_id, repeated),
+        if repeated { " (*)" } else { "" }
+    );
+    if repeated {
+        return;
+    }
+
+    let child_prefix = match last {
+        None => String::new(),
+        Some(false) => format!("{}│   ", prefix),
+        Some(true) => format!("{}    ", prefix),
+    };
+    for (i, child) in below.iter().enumerate() {
+        let last = i + 1 == below.len();
+        print_branch(*child, &child_prefix, Some(last), seen, children, label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo::core::SourceId;
+    use std::path::Path;
+
+    #[test]
+    fn test_subtree_bytes_counts_shared_dependencies_once() {
+        let source = SourceId::for_path(Path::new("/work")).unwrap();
+        let id = |name| PackageId::try_new(name, "1.0.0", source).unwrap();
+        let (app, left, right, shared) = (id("app"), id("left"), id("right"), id("shared"));
+        let dependencies = HashMap::from([
+            (app, BTreeSet::from([left, right])),
+            (left, BTreeSet::from([shared])),
+            (right, BTreeSet::from([shared])),
+            (shared, BTreeSet::new()),
+        ]);
+        let sizes = HashMap::from([
+            (app, PackageSize::with_bytes(1)),
+            (left, PackageSize::with_bytes(10)),
+            (right, PackageSize::with_bytes(100)),
+            (shared, PackageSize::with_bytes(1000)),
+        ]);
+
+        let bytes = subtree_bytes(&dependencies, &sizes);
+        assert_eq!(bytes[&app], 1111);
+        assert_eq!(bytes[&left], 1010);
+        assert_eq!(bytes[&shared], 1000);
+    }
+}
@@ -0,0 +1,909 @@
+use crate::cli::{ConcurrencyModel, DepsizeArgs, ErrorPolicy};
+use crate::saturating;
+use anyhow::{Context, Result};
+use fnv::FnvHasher;
+use ignore::WalkState;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Settings shared by every package walk.
+#[derive(Debug, Clone, Copy)]
+pub struct WalkOptions {
+    pub on_error: ErrorPolicy,
+    /// Directories nested deeper than this are not descended into.
+    pub max_depth: Option<usize>,
+    /// Walk each package with several threads instead of one.
+    pub parallel: bool,
+    /// Switch to the parallel walk once a package turns out to have more
+    /// entries than this.
+    pub parallel_above: Option<u64>,
+    /// How many threads the parallel walk uses, `None` to let `ignore` pick.
+    pub threads: Option<usize>,
+    /// Fail instead of measuring nothing when a package's directory is
+    /// missing or unreadable.
+    pub fail_if_missing: bool,
+    /// How many of each package's largest files to keep track of, all of them
+    /// when recording every file.
+    pub top_files: Option<usize>,
+    /// Keep a `Measuring N/M packages` line up to date on stderr.
+    pub progress: bool,
+    /// How the walks are spread over tokio's threads.
+    pub concurrency: ConcurrencyModel,
+    /// Leave out the [`NONDETERMINISTIC_FILES`].
+    pub canonical: bool,
+    /// Only read the metadata of this fraction of the files, chosen by
+    /// [`is_sampled`], and extrapolate the rest.
+    pub sample_rate: Option<f64>,
+    pub sample_seed: u64,
+}
+
+impl WalkOptions {
+    pub fn from_args(args: &DepsizeArgs) -> Self {
+        WalkOptions {
+            on_error: args.on_error,
+            max_depth: args.max_depth_files,
+            parallel: args.parallel_walk,
+            parallel_above: (!args.no_parallel_walk
+                && args.concurrency_model == ConcurrencyModel::Blocking)
+                .then_some(PARALLEL_WALK_ENTRIES),
+            threads: args.walk_threads,
+            fail_if_missing: args.fail_if_missing_cache,
+            top_files: if args.records_files() {
+                Some(usize::MAX)
+            } else {
+                args.top_files
+            },
+            progress: args.shows_progress(),
+            concurrency: args.concurrency_model,
+            canonical: args.canonical,
+            sample_rate: args.sample_rate,
+            sample_seed: args.sample_seed,
+        }
+    }
+}
+
+/// How many entries a package has before the blocking model walks it with
+/// several threads. The parallel walker's threads cost more than they save
+/// on the small crates most dependency trees are made of, and the first
+/// entries walked again are cheap, still in the OS's cache.
+pub const PARALLEL_WALK_ENTRIES: u64 = 1000;
+
+/// Everything measured while walking a single package.
+#[derive(Debug, Clone, Default)]
+pub struct PackageSize {
+    pub bytes: u64,
+    pub files: u64,
+    pub dirs: DirBreakdown,
+    /// Bytes of the files every crate ships besides its sources, see
+    /// [`is_metadata_file`].
+    pub metadata_bytes: u64,
+    /// Whether `max_depth` kept the walk out of some directories, making
+    /// `bytes` a lower bound.
+    pub depth_capped: bool,
+    /// FIFOs, sockets, device files and the like that were left out of the
+    /// total, since their length says nothing about disk usage.
+    pub special_files: u64,
+    /// How long the walk took.
+    pub duration: Duration,
+    /// With `sample_rate`, the files counted in `files` whose size wasn't
+    /// read, and the sum of the squared sizes of those that were.
+    unsampled_files: u64,
+    sampled_squares: f64,
+    /// With `sample_rate`, one of the unsampled files, read instead when the
+    /// sample would otherwise be empty.
+    first_unsampled: Option<PathBuf>,
+    /// With `sample_rate`, the variance of the extrapolated `bytes`, infinite
+    /// when fewer than two files were sampled to estimate it from.
+    pub estimate_variance: f64,
+    /// With `top_files`, the largest files as `(bytes, package-relative
+    /// path)`, smallest on top so it's cheap to drop.
+    largest_files: BinaryHeap<Reverse<(u64, PathBuf)>>,
+}
+
+impl PackageSize {
+    /// A package known only by its total size, without walking it.
+    pub fn with_bytes(bytes: u64) -> Self {
+        PackageSize {
+            bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a partial measurement of the same package.
+    fn merge(&mut self, other: &PackageSize) {
+        saturating::add(&mut self.bytes, other.bytes);
+        saturating::add(&mut self.files, other.files);
+        self.dirs.merge(&other.dirs);
+        saturating::add(&mut self.metadata_bytes, other.metadata_bytes);
+        self.depth_capped |= other.depth_capped;
+        saturating::add(&mut self.special_files, other.special_files);
+        saturating::add(&mut self.unsampled_files, other.unsampled_files);
+        self.add_sampled_square(other.sampled_squares);
+        if self.first_unsampled.is_none() {
+            self.first_unsampled.clone_from(&other.first_unsampled);
+        }
+        self.largest_files
+            .extend(other.largest_files.iter().cloned());
+    }
+
+    /// Adds to `sampled_squares`, stopping at the largest finite value so
+    /// the variance never becomes infinite.
+    fn add_sampled_square(&mut self, square: f64) {
+        self.sampled_squares = (self.sampled_squares + square).min(f64::MAX);
+    }
+
+    /// Records a file for `top_files`, keeping only the `limit` largest.
+    fn add_large_file(&mut self, bytes: u64, relative_path: &Path, limit: usize) {
+        self.largest_files
+            .push(Reverse((bytes, relative_path.to_path_buf())));
+        while self.largest_files.len() > limit {
+            self.largest_files.pop();
+        }
+    }
+
+    /// Scales the bytes of the sampled files up to all of them, and keeps
+    /// the variance of that estimate. The sampled files are a simple random
+    /// sample, so it's `N² s² / n` with the finite population correction.
+    ///
+    /// An empty sample gets one unsampled file read after all, so that no
+    /// package is estimated to be empty just because none of its files was
+    /// picked.
+    fn extrapolate(&mut self) {
+        if self.unsampled_files == 0 {
+            return;
+        }
+        if self.files == self.unsampled_files {
+            let metadata = self
+                .first_unsampled
+                .as_deref()
+                .and_then(|path| std::fs::symlink_metadata(path).ok());
+            if let Some(metadata) = metadata {
+                saturating::add(&mut self.bytes, metadata.len());
+                self.add_sampled_square((metadata.len() as f64).powi(2));
+                self.unsampled_files -= 1;
+            }
+        }
+        let all = self.files as f64;
+        let sampled = (self.files - self.unsampled_files) as f64;
+        if sampled < 2.0 {
+            // Nothing to tell how far off the extrapolation may be
+            self.bytes = (self.bytes as f64 * all / sampled.max(1.0)).round() as u64;
+            self.estimate_variance = f64::INFINITY;
+            return;
+        }
+        let mean = self.bytes as f64 / sampled;
+        let sample_variance =
+            ((self.sampled_squares - sampled * mean * mean) / (sampled - 1.0)).max(0.0);
+        self.bytes = (mean * all).round() as u64;
+        self.estimate_variance = all * all * sample_variance / sampled * (1.0 - sampled / all);
+    }
+
+    /// The largest files kept for `top_files`, largest first.
+    pub fn largest_files(&self) -> Vec<(u64, PathBuf)> {
+        self.largest_files
+            .clone()
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(file)| file)
+            .collect()
+    }
+}
+
+/// Bytes of a package split by top-level directory.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DirBreakdown {
+    pub src: u64,
+    pub tests: u64,
+    pub examples: u64,
+    pub benches: u64,
+    pub other: u64,
+}
+
+impl DirBreakdown {
+    /// Adds a file, classified by the first component of its package-relative path.
+    fn add(&mut self, relative_path: &Path, bytes: u64) {
+        let mut components = relative_path.components();
+        // Only files *inside* a directory count towards it, not a file named `src`
+        let dir = match (components.next(), components.next()) {
+            (Some(dir), Some(_)) => dir.as_os_str().to_str(),
+            _ => None,
+        };
+        let bucket = match dir {
+            Some("src") => &mut self.src,
+            Some("tests") => &mut self.tests,
+            Some("examples") => &mut self.examples,
+            Some("benches") => &mut self.benches,
+            _ => &mut self.other,
+        };
+        saturating::add(bucket, bytes);
+    }
+
+    fn merge(&mut self, other: &DirBreakdown) {
+        saturating::add(&mut self.src, other.src);
+        saturating::add(&mut self.tests, other.tests);
+        saturating::add(&mut self.examples, other.examples);
+        saturating::add(&mut self.benches, other.benches);
+        saturating::add(&mut self.other, other.other);
+    }
+
+    /// The buckets in display order, labelled.
+    pub fn buckets(&self) -> [(&'static str, u64); 5] {
+        [
+            ("src/", self.src),
+            ("tests/", self.tests),
+            ("examples/", self.examples),
+            ("benches/", self.benches),
+            ("other", self.other),
+        ]
+    }
+}
+
+/// Walks a package directory and sums the size of every file in it,
+/// honouring `.gitignore` and friends the same way cargo does.
+///
+/// With [`ErrorPolicy::Skip`] unreadable entries are reported on stderr and
+/// left out of the total; with [`ErrorPolicy::Fail`] the first one aborts the
+/// walk.
+///
+/// The walk is blocking, so this belongs on a blocking thread. It stops early
+/// and returns `None` once `cancelled` is set.
+pub fn calculate_package_size(
+    package_path: &Path,
+    options: WalkOptions,
+    cancelled: &AtomicBool,
+) -> Result<Option<PackageSize>> {
+    // So that entry paths keep starting with it
+    let package_path = &walk_root(package_path);
+    if options.fail_if_missing {
+        std::fs::read_dir(package_path).with_context(|| {
+            format!(
+                "the package sources at {} are missing or unreadable",
+                package_path.display()
+            )
+        })?;
+    }
+
+    let walker = package_walker(package_path, options);
+    if options.parallel {
+        return walk_parallel(&walker, package_path, options, cancelled);
+    }
+
+    let mut size = PackageSize::default();
+    for (entries, entry) in (1..).zip(walker.build()) {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        if options.parallel_above.is_some_and(|limit| entries > limit) {
+            // Starting over costs less than walking the rest on one thread
+            return walk_parallel(&walker, package_path, options, cancelled);
+        }
+        add_entry(entry, package_path, options, &mut size)?;
+    }
+
+    size.extrapolate();
+    Ok(Some(size))
+}
+
+/// Like [`calculate_package_size`], but meant to run as an ordinary tokio
+/// task, reading each file's metadata with `tokio::fs`.
+async fn calculate_package_size_async(
+    package_path: &Path,
+    options: WalkOptions,
+    cancelled: &AtomicBool,
+) -> Result<Option<PackageSize>> {
+    let package_path = &walk_root(package_path);
+    if options.fail_if_missing {
+        let _ = tokio::fs::read_dir(package_path).await.with_context(|| {
+            format!(
+                "the package sources at {} are missing or unreadable",
+                package_path.display()
+            )
+        })?;
+    }
+
+    let mut size = PackageSize::default();
+    for entry in package_walker(package_path, options).build() {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        add_entry_async(entry, package_path, options, &mut size).await?;
+    }
+
+    size.extrapolate();
+    Ok(Some(size))
+}
+
+/// The walk over a package's files that every measurement shares.
+///
+/// Hidden files are counted, since `cargo package` ships them too, but a
+/// `.git` directory never is. Symbolic links are never followed, and on
+/// Windows neither are junctions or other directory reparse points.
+pub fn package_walker(package_path: &Path, options: WalkOptions) -> ignore::WalkBuilder {
+    let canonical = options.canonical;
+    let mut walker = ignore::WalkBuilder::new(walk_root(package_path));
+    walker
+        .hidden(false)
+        .max_depth(options.max_depth)
+        .filter_entry(move |entry| {
+            let excluded = entry.file_name() == ".git"
+                || is_cargo_target_dir(entry)
+                || is_reparse_dir(entry)
+                || (canonical && is_nondeterministic_file(entry));
+            !excluded
+        });
+    if let Some(threads) = options.threads {
+        walker.threads(threads);
+    }
+    walker
+}
+
+/// The files at the root of an unpacked crate that `--canonical` leaves out,
+/// since they differ between caches of the same crate version.
+pub const NONDETERMINISTIC_FILES: &[&str] = &[".cargo-ok", ".cargo_vcs_info.json"];
+
+fn is_nondeterministic_file(entry: &ignore::DirEntry) -> bool {
+    entry.depth() == 1
+        && entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| NONDETERMINISTIC_FILES.contains(&name))
+}
+
+/// Where to start walking the package at `package_path`.
+///
+/// On Windows this is the extended-length (`\\?\`) form of the path, which
+/// `canonicalize` returns there, so that files nested deeper than `MAX_PATH`
+/// (260 characters) in the cache can still be read.
+#[cfg(windows)]
+fn walk_root(package_path: &Path) -> PathBuf {
+    std::fs::canonicalize(package_path).unwrap_or_else(|_| package_path.to_path_buf())
+}
+
+#[cfg(not(windows))]
+fn walk_root(package_path: &Path) -> PathBuf {
+    package_path.to_path_buf()
+}
+
+/// Whether an entry is a directory junction, mount point or other reparse
+/// point, whose target may be anywhere (or loop back into the package).
+///
+/// `std` already reports junctions as symlinks, which aren't followed, this
+/// also catches the reparse points it doesn't.
+#[cfg(windows)]
+fn is_reparse_dir(entry: &ignore::DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+    entry.depth() > 0
+        && entry.metadata().is_ok_and(|metadata| {
+            let attributes = metadata.file_attributes();
+            attributes & FILE_ATTRIBUTE_DIRECTORY != 0
+                && attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0
+        })
+}
+
+#[cfg(not(windows))]
+fn is_reparse_dir(_entry: &ignore::DirEntry) -> bool {
+    false
+}
+
+/// Like the sequential walk in [`calculate_package_size`], but spread over
+/// `ignore`'s walker threads. Each thread keeps its own subtotal and adds it
+/// to the package's total when it finishes.
+fn walk_parallel(
+    walker: &ignore::WalkBuilder,
+    package_path: &Path,
+    options: WalkOptions,
+    cancelled: &AtomicBool,
+) -> Result<Option<PackageSize>> {
+    struct Subtotal<'a> {
+        size: PackageSize,
+        total: &'a Mutex<PackageSize>,
+    }
+
+    impl Subtotal<'_> {
+        // A method, so the closure below captures (and drops) the whole
+        // subtotal rather than just copying its `size` field
+        fn add(
+            &mut self,
+            entry: Result<ignore::DirEntry, ignore::Error>,
+            package_path: &Path,
+            options: WalkOptions,
+        ) -> Result<()> {
+            add_entry(entry, package_path, options, &mut self.size)
+        }
+    }
+
+    impl Drop for Subtotal<'_> {
+        fn drop(&mut self) {
+            self.total.lock().unwrap().merge(&self.size);
+        }
+    }
+
+    let total = Mutex::new(PackageSize::default());
+    let first_error = Mutex::new(None);
+
+    walker.build_parallel().run(|| {
+        let mut subtotal = Subtotal {
+            size: PackageSize::default(),
+            total: &total,
+        };
+        let first_error = &first_error;
+        Box::new(move |entry| {
+            if cancelled.load(Ordering::Relaxed) {
+                return WalkState::Quit;
+            }
+            match subtotal.add(entry, package_path, options) {
+                Ok(()) => WalkState::Continue,
+                Err(err) => {
+                    first_error.lock().unwrap().get_or_insert(err);
+                    WalkState::Quit
+                }
+            }
+        })
+    });
+
+    if let Some(err) = first_error.into_inner().unwrap() {
+        return Err(err);
+    }
+    if cancelled.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+    let mut total = total.into_inner().unwrap();
+    // Each thread kept its own largest files
+    if let Some(limit) = options.top_files {
+        while total.largest_files.len() > limit {
+            total.largest_files.pop();
+        }
+    }
+    total.extrapolate();
+    Ok(Some(total))
+}
+
+/// Adds one entry of the walk to `size`.
+fn add_entry(
+    entry: Result<ignore::DirEntry, ignore::Error>,
+    package_path: &Path,
+    options: WalkOptions,
+    size: &mut PackageSize,
+) -> Result<()> {
+    let Some(file) = visit_entry(entry, options, size)? else {
+        return Ok(());
+    };
+    if skip_unsampled(file.path(), package_path, options, size) {
+        return Ok(());
+    }
+    // On Windows the walker already has the metadata from listing the
+    // directory; elsewhere this is a plain lstat
+    let metadata = file
+        .metadata()
+        .with_context(|| format!("failed to read metadata of {}", file.path().display()));
+    add_file(file.path(), metadata, package_path, options, size)
+}
+
+/// Like [`add_entry`], but the file's metadata is read with `tokio::fs`, for
+/// `--concurrency-model tasks`.
+async fn add_entry_async(
+    entry: Result<ignore::DirEntry, ignore::Error>,
+    package_path: &Path,
+    options: WalkOptions,
+    size: &mut PackageSize,
+) -> Result<()> {
+    let Some(file) = visit_entry(entry, options, size)? else {
+        return Ok(());
+    };
+    if skip_unsampled(file.path(), package_path, options, size) {
+        return Ok(());
+    }
+    let metadata = tokio::fs::symlink_metadata(file.path())
+        .await
+        .with_context(|| format!("failed to read metadata of {}", file.path().display()));
+    add_file(file.path(), metadata, package_path, options, size)
+}
+
+/// Counts a non-file entry of the walk in `size`, or returns it if it's a
+/// file whose size still has to be added.
+fn visit_entry(
+    entry: Result<ignore::DirEntry, ignore::Error>,
+    options: WalkOptions,
+    size: &mut PackageSize,
+) -> Result<Option<ignore::DirEntry>> {
+    let entry = match entry {
+        Ok(entry) => entry,
+        Err(err) => return options.on_error.handle(err.into()).map(|()| None),
+    };
+
+    // Only entries read from stdin, which this walk never does, have no type
+    let Some(file_type) = entry.file_type() else {
+        return Ok(None);
+    };
+    // An empty directory at the limit hides nothing
+    if file_type.is_dir()
+        && Some(entry.depth()) == options.max_depth
+        && std::fs::read_dir(entry.path()).is_ok_and(|mut entries| entries.next().is_some())
+    {
+        size.depth_capped = true;
+    }
+
+    if !file_type.is_file() && !file_type.is_dir() && !file_type.is_symlink() {
+        saturating::add(&mut size.special_files, 1);
+    }
+
+    Ok(file_type.is_file().then_some(entry))
+}
+
+/// With `sample_rate`, counts a file that isn't part of the sample without
+/// reading its metadata, returning whether it was.
+fn skip_unsampled(
+    path: &Path,
+    package_path: &Path,
+    options: WalkOptions,
+    size: &mut PackageSize,
+) -> bool {
+    let Some(rate) = options.sample_rate else {
+        return false;
+    };
+    let relative_path = path.strip_prefix(package_path).unwrap_or(path);
+    if is_sampled(relative_path, rate, options.sample_seed) {
+        return false;
+    }
+    saturating::add(&mut size.files, 1);
+    saturating::add(&mut size.unsampled_files, 1);
+    if size.first_unsampled.is_none() {
+        size.first_unsampled = Some(path.to_path_buf());
+    }
+    true
+}
+
+/// Whether a file belongs to the `rate` sample, picked by hashing its
+/// package-relative path with `seed`, so the same files are picked on
+/// every run and by every walker thread.
+///
+/// FNV is fixed by its specification, unlike `std`'s hashers, so upgrading
+/// the toolchain doesn't change the sample. The path is hashed as bytes in
+/// its `/`-separated form, the same on every platform.
+fn is_sampled(relative_path: &Path, rate: f64, seed: u64) -> bool {
+    let mut hasher = FnvHasher::default();
+    hasher.write(&seed.to_le_bytes());
+    for component in relative_path.components() {
+        hasher.write(component.as_os_str().as_encoded_bytes());
+        hasher.write(b"/");
+    }
+    (hasher.finish() as f64 / u64::MAX as f64) < rate
+}
+
+/// Adds a file of the package at `package_path` to `size`.
+fn add_file(
+    path: &Path,
+    metadata: Result<std::fs::Metadata>,
+    package_path: &Path,
+    options: WalkOptions,
+    size: &mut PackageSize,
+) -> Result<()> {
+    let metadata = match metadata {
+        Ok(metadata) => metadata,
+        Err(err) => return options.on_error.handle(err),
+    };
+    let relative_path = path.strip_prefix(package_path)?;
+    saturating::add(&mut size.bytes, metadata.len());
+    saturating::add(&mut size.files, 1);
+    if options.sample_rate.is_some() {
+        size.add_sampled_square((metadata.len() as f64).powi(2));
+    }
+    size.dirs.add(relative_path, metadata.len());
+    if is_metadata_file(relative_path) {
+        saturating::add(&mut size.metadata_bytes, metadata.len());
+    }
+    if let Some(limit) = options.top_files {
+        size.add_large_file(metadata.len(), relative_path, limit);
+    }
+    Ok(())
+}
+
+/// Whether a package-relative path is one of the files packaging adds to
+/// every crate, whatever its code: the manifest, its original, the VCS info
+/// and a README at the root.
+fn is_metadata_file(relative_path: &Path) -> bool {
+    if relative_path.components().count() != 1 {
+        return false;
+    }
+    let Some(name) = relative_path.to_str() else {
+        return false;
+    };
+    matches!(
+        name,
+        "Cargo.toml" | "Cargo.toml.orig" | ".cargo_vcs_info.json"
+    ) || name.to_ascii_lowercase().starts_with("readme")
+}
+
+/// Whether an entry is a cargo build directory at the root of a package.
+///
+/// Registry crates never ship one, but local checkouts pulled in through
+/// `path` dependencies or `[patch]` often have one that isn't gitignored
+/// (or isn't in a git repository at all), which would dwarf the sources.
+fn is_cargo_target_dir(entry: &ignore::DirEntry) -> bool {
+    entry.depth() == 1
+        && entry.file_name() == "target"
+        && entry.path().join("CACHEDIR.TAG").is_file()
+}
+
+/// The sizes measured by [`measure_packages`].
+#[derive(Debug)]
+pub struct Measured<K> {
+    pub sizes: HashMap<K, PackageSize>,
+    /// Whether Ctrl-C cut the scan short, leaving some packages out of `sizes`.
+    pub interrupted: bool,
+}
+
+/// Measures every `(key, label, path)` concurrently, one task per package:
+/// on tokio's blocking pool by default, or as ordinary tasks with
+/// [`ConcurrencyModel::Tasks`].
+///
+/// The label names the package in error messages. Packages sharing a path,
+/// as some source replacement setups make them, are walked once and all get
+/// that size. On Ctrl-C the walks still running are cancelled and the ones
+/// already finished are returned.
+pub async fn measure_packages<K>(
+    packages: Vec<(K, String, PathBuf)>,
+    options: WalkOptions,
+) -> Result<Measured<K>>
+where
+    K: Eq + Hash + Send + 'static,
+{
+    measure_packages_with(packages, options, |_, _| Ok(())).await
+}
+
+/// Like [`measure_packages`], but also calls `on_measured` with each package
+/// as soon as its walk finishes, for `--format ndjson-stream`.
+pub async fn measure_packages_with<K, F>(
+    packages: Vec<(K, String, PathBuf)>,
+    options: WalkOptions,
+    mut on_measured: F,
+) -> Result<Measured<K>>
+where
+    K: Eq + Hash + Send + 'static,
+    F: FnMut(&K, &PackageSize) -> Result<()>,
+{
+    let mut join_set = JoinSet::new();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let total = packages.len();
+    let mut done = 0;
+
+    // Every path with the packages found there, in the order first seen
+    let mut roots: Vec<(PathBuf, Vec<(K, String)>)> = Vec::new();
+    let mut root_indices = HashMap::new();
+    for (key, label, package_path) in packages {
+        let index = *root_indices.entry(package_path.clone()).or_insert_with(|| {
+            roots.push((package_path, Vec::new()));
+            roots.len() - 1
+        });
+        roots[index].1.push((key, label));
+    }
+
+    // Spawn each calculate_package_size task into the JoinSet
+    for (package_path, sharing) in roots {
+        let cancelled = Arc::clone(&cancelled);
+        let finish = move |start: Instant, size: Result<Option<PackageSize>>| -> Result<_> {
+            let size = size.with_context(|| {
+                let labels: Vec<&str> = sharing.iter().map(|(_, label)| label.as_str()).collect();
+                format!("failed to calculate size for {}", labels.join(", "))
+            })?;
+            Ok(size.map(|mut size| {
+                size.duration = start.elapsed();
+                (sharing, size)
+            }))
+        };
+        match options.concurrency {
+            ConcurrencyModel::Blocking => join_set.spawn_blocking(move || {
+                let start = Instant::now();
+                finish(
+                    start,
+                    calculate_package_size(&package_path, options, &cancelled),
+                )
+            }),
+            ConcurrencyModel::Tasks => join_set.spawn(async move {
+                let start = Instant::now();
+                let size = calculate_package_size_async(&package_path, options, &cancelled).await;
+                finish(start, size)
+            }),
+        };
+    }
+
+    let mut sizes = HashMap::new();
+
+    // Await all spawned tasks and collect their results
+    // (returning early drops the JoinSet, which aborts the remaining walks)
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut interrupted = false;
+    loop {
+        let res = tokio::select! {
+            res = join_set.join_next() => match res {
+                Some(res) => res,
+                None => break,
+            },
+            _ = &mut ctrl_c, if !interrupted => {
+                // Cancel the walks that haven't finished yet, but keep draining
+                // the set for the ones that already have. Aborting only stops
+                // walks that haven't started, running ones check the flag
+                interrupted = true;
+                cancelled.store(true, Ordering::Relaxed);
+                join_set.abort_all();
+                continue;
+            }
+        };
+        if matches!(&res, Err(err) if err.is_cancelled()) {
+            continue;
+        }
+        if let Some((sharing, size)) = res?? {
+            if options.progress {
+                done += sharing.len();
+                eprint!("\r  Measuring {}/{} packages", done, total);
+            }
+            for (key, _) in sharing {
+                on_measured(&key, &size)?;
+                sizes.insert(key, size.clone());
+            }
+        }
+    }
+
+    if options.progress && done > 0 {
+        // Clear the progress line
+        eprint!("\r\x1b[K");
+    }
+    Ok(Measured { sizes, interrupted })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CargoCli;
+    use crate::report::Estimate;
+    use clap::Parser;
+
+    #[test]
+    fn test_extrapolate_scales_the_sample_up() {
+        let mut size = PackageSize {
+            bytes: 30,
+            files: 4,
+            unsampled_files: 2,
+            sampled_squares: 10.0 * 10.0 + 20.0 * 20.0,
+            ..Default::default()
+        };
+        size.extrapolate();
+        assert_eq!(size.bytes, 60);
+        // s² = 50, so 4² * 50 / 2 * (1 - 2/4)
+        assert_eq!(size.estimate_variance, 200.0);
+
+        let sampled = (0..1000)
+            .filter(|i| is_sampled(Path::new(&format!("src/{}.rs", i)), 0.1, 0))
+            .count();
+        assert!((50..150).contains(&sampled), "{}", sampled);
+        assert!(is_sampled(Path::new("src/lib.rs"), 1.0, 7));
+    }
+
+    #[test]
+    fn test_empty_sample_reads_one_file_and_has_no_margin() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "x".repeat(100)).unwrap();
+        std::fs::write(dir.path().join("main.rs"), "x".repeat(100)).unwrap();
+        let CargoCli::Depsize(args) =
+            CargoCli::parse_from(["cargo", "depsize", "--sample-rate", "0.0000001"]);
+        let size = calculate_package_size(
+            dir.path(),
+            WalkOptions::from_args(&args),
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!((size.bytes, size.files), (200, 2));
+        assert!(size.estimate_variance.is_infinite());
+        assert_eq!(
+            Estimate::new(0.1, [size.estimate_variance]).margin_bytes,
+            None
+        );
+    }
+
+    #[test]
+    fn test_large_packages_switch_to_the_parallel_walk_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..PARALLEL_WALK_ENTRIES + 10 {
+            std::fs::write(dir.path().join(format!("{}.rs", i)), "x").unwrap();
+        }
+        for flags in [&[][..], &["--parallel-walk"], &["--no-parallel-walk"]] {
+            let CargoCli::Depsize(args) =
+                CargoCli::parse_from(["cargo", "depsize"].iter().chain(flags));
+            let size = calculate_package_size(
+                dir.path(),
+                WalkOptions::from_args(&args),
+                &AtomicBool::new(false),
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(size.files, PARALLEL_WALK_ENTRIES + 10, "{:?}", flags);
+            assert_eq!(size.bytes, PARALLEL_WALK_ENTRIES + 10, "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn test_depth_capped_only_when_something_was_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/empty")).unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        let CargoCli::Depsize(args) =
+            CargoCli::parse_from(["cargo", "depsize", "--max-depth-files", "2"]);
+        let options = WalkOptions::from_args(&args);
+        let measure = || {
+            calculate_package_size(dir.path(), options, &AtomicBool::new(false))
+                .unwrap()
+                .unwrap()
+        };
+        assert!(!measure().depth_capped);
+
+        std::fs::write(dir.path().join("src/empty/deep.rs"), "").unwrap();
+        assert!(measure().depth_capped);
+    }
+
+    #[test]
+    fn test_canonical_excludes_nondeterministic_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join(".git/HEAD"), "x".repeat(1000)).unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "x".repeat(100)).unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "x".repeat(10)).unwrap();
+        std::fs::write(dir.path().join(".cargo-ok"), "x".repeat(2)).unwrap();
+        std::fs::write(dir.path().join(".cargo_vcs_info.json"), "x".repeat(3)).unwrap();
+        let bytes = |args: &[&str]| {
+            let CargoCli::Depsize(args) =
+                CargoCli::parse_from(["cargo", "depsize"].iter().chain(args));
+            calculate_package_size(
+                dir.path(),
+                WalkOptions::from_args(&args),
+                &AtomicBool::new(false),
+            )
+            .unwrap()
+            .unwrap()
+            .bytes
+        };
+        assert_eq!(bytes(&[]), 115);
+        assert_eq!(bytes(&["--canonical"]), 110);
+    }
+
+    #[test]
+    fn test_is_metadata_file_only_matches_root_files() {
+        assert!(is_metadata_file(Path::new("Cargo.toml")));
+        assert!(is_metadata_file(Path::new("Cargo.toml.orig")));
+        assert!(is_metadata_file(Path::new(".cargo_vcs_info.json")));
+        assert!(is_metadata_file(Path::new("README.md")));
+        assert!(is_metadata_file(Path::new("Readme")));
+        assert!(!is_metadata_file(Path::new("src/README.md")));
+        assert!(!is_metadata_file(Path::new("examples/Cargo.toml")));
+        assert!(!is_metadata_file(Path::new("LICENSE")));
+    }
+
+    #[test]
+    fn test_dir_breakdown_classifies_by_top_level_dir() {
+        let mut dirs = DirBreakdown::default();
+        dirs.add(Path::new("src/lib.rs"), 10);
+        dirs.add(Path::new("src/nested/mod.rs"), 5);
+        dirs.add(Path::new("tests/it.rs"), 3);
+        dirs.add(Path::new("examples/demo.rs"), 2);
+        dirs.add(Path::new("benches/bench.rs"), 1);
+        dirs.add(Path::new("Cargo.toml"), 7);
+        dirs.add(Path::new("src"), 4);
+
+        assert_eq!(dirs.src, 15);
+        assert_eq!(dirs.tests, 3);
+        assert_eq!(dirs.examples, 2);
+        assert_eq!(dirs.benches, 1);
+        assert_eq!(dirs.other, 11);
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use cargo::core::{PackageId, PackageSet};
+use cargo::util::cache_lock::CacheLockMode;
+use cargo::GlobalContext;
+use std::collections::HashSet;
+use std::task::Poll;
+
+/// The registry packages among `package_ids` whose version has been yanked.
+///
+/// Asks the registry sources `pkg_set` was downloaded with, so the index
+/// entries already loaded while resolving are reused rather than fetched
+/// again. Packages whose index entry can't be read, e.g. offline with an
+/// empty index cache, are assumed not to be yanked, with a warning.
+pub fn yanked_packages(
+    gctx: &GlobalContext,
+    pkg_set: &PackageSet<'_>,
+    package_ids: impl IntoIterator<Item = PackageId>,
+) -> Result<HashSet<PackageId>> {
+    let _lock = gctx.acquire_package_cache_lock(CacheLockMode::DownloadExclusive)?;
+    let mut sources = pkg_set.sources_mut();
+    let mut yanked = HashSet::new();
+    for package_id in package_ids {
+        if !package_id.source_id().is_registry() {
+            continue;
+        }
+        let Some(source) = sources.get_mut(package_id.source_id()) else {
+            continue;
+        };
+        let is_yanked = loop {
+            match source.is_yanked(package_id) {
+                Poll::Ready(is_yanked) => break is_yanked,
+                Poll::Pending => source.block_until_ready()?,
+            }
+        };
+        match is_yanked {
+            Ok(true) => {
+                yanked.insert(package_id);
+            }
+            Ok(false) => {}
+            Err(err) => eprintln!(
+                "warning: couldn't tell whether {} v{} is yanked: {:#}",
+                package_id.name(),
+                package_id.version(),
+                err
+            ),
+        }
+    }
+    Ok(yanked)
+}